@@ -0,0 +1,40 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::ApiSpace;
+use crate::HitTestResult;
+use crate::Native;
+use crate::Space;
+
+use euclid::RigidTransform3D;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnchorId(pub u32);
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// The coordinate space of an anchor
+pub struct AnchorSpace;
+
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// Where to create an anchor
+pub enum AnchorSource {
+    /// A pose relative to a space
+    /// https://immersive-web.github.io/anchors/#dom-xrframe-createanchor
+    Space(Space, RigidTransform3D<f32, AnchorSpace, ApiSpace>),
+    /// The pose of a hit test result
+    /// https://immersive-web.github.io/anchors/#dom-xrhittestresult-createanchor
+    HitTest(HitTestResult),
+}
+
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// The pose of a tracked anchor for a frame
+/// https://immersive-web.github.io/anchors/#xranchor
+pub struct AnchorPose {
+    pub id: AnchorId,
+    pub pose: RigidTransform3D<f32, AnchorSpace, Native>,
+}
@@ -0,0 +1,148 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! What a device can do for a session, so that requests it can't handle
+//! are rejected before they reach the device.
+
+use crate::AnchorSource;
+use crate::BaseSpace;
+use crate::EntityTypes;
+use crate::Error;
+use crate::HitTestSource;
+use crate::LayerInit;
+use crate::LayerLayout;
+use crate::LayerType;
+use crate::ReferenceSpaceType;
+use crate::Space;
+use crate::TransientHitTestSource;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct DeviceCapabilities {
+    /// The features granted to the session
+    pub features: Vec<String>,
+    pub reference_spaces: Vec<ReferenceSpaceType>,
+    /// The entities that hit tests can find, which is none of them if hit tests are unsupported
+    pub entity_types: EntityTypes,
+    pub layer_types: Vec<LayerType>,
+    /// Whether layers can be allocated as texture arrays
+    pub texture_arrays: bool,
+}
+
+impl DeviceCapabilities {
+    /// The capabilities of a device which supports the reference spaces
+    /// that go with its features, and WebGL and projection layers.
+    pub fn new(features: Vec<String>, has_floor: bool) -> DeviceCapabilities {
+        let mut reference_spaces = vec![ReferenceSpaceType::Viewer, ReferenceSpaceType::Local];
+        if has_floor {
+            reference_spaces.push(ReferenceSpaceType::LocalFloor);
+        }
+        if features.iter().any(|f| f == "bounded-floor") {
+            reference_spaces.push(ReferenceSpaceType::BoundedFloor);
+        }
+        if features.iter().any(|f| f == "unbounded") {
+            reference_spaces.push(ReferenceSpaceType::Unbounded);
+        }
+        DeviceCapabilities {
+            features,
+            reference_spaces,
+            entity_types: EntityTypes::default(),
+            layer_types: vec![LayerType::WebGL, LayerType::Projection],
+            texture_arrays: false,
+        }
+    }
+
+    pub fn supports_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    pub fn supports_space(&self, space: Space) -> bool {
+        match space.base {
+            BaseSpace::TargetRay(_) | BaseSpace::Grip(_) => true,
+            BaseSpace::Joint(..) => self.supports_feature("hand-tracking"),
+            base => match base.reference_space_type() {
+                Some(ty) => self.reference_spaces.contains(&ty),
+                None => false,
+            },
+        }
+    }
+
+    pub fn check_hit_test(&self, source: &HitTestSource) -> Result<(), Error> {
+        self.check_space(source.space)?;
+        self.check_entity_types(source.types)
+    }
+
+    pub fn check_transient_hit_test(&self, source: &TransientHitTestSource) -> Result<(), Error> {
+        self.check_entity_types(source.types)
+    }
+
+    pub fn check_anchor(&self, source: &AnchorSource) -> Result<(), Error> {
+        if !self.supports_feature("anchors") {
+            return Err(Error::FeatureNotGranted("anchors".into()));
+        }
+        match *source {
+            AnchorSource::Space(space, _) => self.check_space(space),
+            AnchorSource::HitTest(_) => Ok(()),
+        }
+    }
+
+    pub fn check_layer(&self, init: &LayerInit) -> Result<(), Error> {
+        let layer_type = init.layer_type();
+        if !self.layer_types.contains(&layer_type) {
+            return Err(Error::UnsupportedFeature(format!(
+                "{:?} layers",
+                layer_type
+            )));
+        }
+        let layout = init.layout();
+        if layout == LayerLayout::TextureArray && !self.texture_arrays {
+            return Err(Error::UnsupportedFeature("texture array layers".into()));
+        }
+        let supports_layout = match layer_type {
+            LayerType::WebGL => layout == LayerLayout::Default,
+            LayerType::Projection => {
+                layout == LayerLayout::Default || layout == LayerLayout::TextureArray
+            }
+            LayerType::Quad | LayerType::Cylinder | LayerType::Equirect | LayerType::Cube => {
+                layout != LayerLayout::Default
+            }
+        };
+        if !supports_layout {
+            return Err(Error::UnsupportedFeature(format!(
+                "{:?} layout for {:?} layers",
+                layout, layer_type
+            )));
+        }
+        match init.space() {
+            Some(space) => self.check_space(space),
+            None => Ok(()),
+        }
+    }
+
+    fn check_space(&self, space: Space) -> Result<(), Error> {
+        if self.supports_space(space) {
+            Ok(())
+        } else {
+            Err(Error::UnsupportedFeature(format!("{:?} space", space.base)))
+        }
+    }
+
+    fn check_entity_types(&self, types: EntityTypes) -> Result<(), Error> {
+        if !self.supports_feature("hit-test") {
+            return Err(Error::FeatureNotGranted("hit-test".into()));
+        }
+        let supported = self.entity_types;
+        if (types.point && supported.point)
+            || (types.plane && supported.plane)
+            || (types.mesh && supported.mesh)
+        {
+            Ok(())
+        } else {
+            Err(Error::UnsupportedFeature(format!(
+                "hit tests against {:?}",
+                types
+            )))
+        }
+    }
+}
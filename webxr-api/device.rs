@@ -4,7 +4,11 @@
 
 //! Traits to be implemented by backends
 
+use crate::AnchorId;
+use crate::AnchorSource;
 use crate::ContextId;
+use crate::DeviceCapabilities;
+use crate::DeviceNotifier;
 use crate::EnvironmentBlendMode;
 use crate::Error;
 use crate::Event;
@@ -22,10 +26,44 @@ use crate::Session;
 use crate::SessionBuilder;
 use crate::SessionInit;
 use crate::SessionMode;
+use crate::TransientHitTestSource;
 use crate::Viewports;
+use crate::Visibility;
 
+use euclid::Point3D;
 use euclid::RigidTransform3D;
 
+#[cfg(feature = "ipc")]
+use serde::{Deserialize, Serialize};
+
+/// The id the registry gives a device when it is registered
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
+pub struct DeviceId(pub(crate) u32);
+
+/// A description of a device, so the embedder can choose between devices
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
+pub struct DeviceDescriptor {
+    pub name: String,
+    pub vendor: String,
+    pub modes: Vec<SessionMode>,
+    /// The optional features the device may grant, which may depend on the hardware
+    pub features: Vec<String>,
+}
+
+/// A change to the devices which are available
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
+pub enum DeviceEvent {
+    /// A device was registered or plugged in
+    Added(DeviceId, DeviceDescriptor),
+    /// A device was unplugged
+    Removed(DeviceId),
+    /// A device's descriptor changed
+    Changed(DeviceId, DeviceDescriptor),
+}
+
 /// A trait for discovering XR devices
 pub trait DiscoveryAPI<GL>: 'static {
     fn request_session(
@@ -35,6 +73,29 @@ pub trait DiscoveryAPI<GL>: 'static {
         xr: SessionBuilder<GL>,
     ) -> Result<Session, Error>;
     fn supports_session(&self, mode: SessionMode) -> bool;
+
+    /// Discoveries which can notice devices being plugged in or unplugged
+    /// should keep the notifier the registry gives them, and use it to say so
+    fn set_device_notifier(&mut self, _notifier: DeviceNotifier) {}
+
+    /// By default, devices are just described by the modes they support
+    fn descriptor(&self) -> DeviceDescriptor {
+        let modes = [
+            SessionMode::Inline,
+            SessionMode::ImmersiveVR,
+            SessionMode::ImmersiveAR,
+        ];
+        DeviceDescriptor {
+            name: "XR device".into(),
+            vendor: String::new(),
+            modes: modes
+                .iter()
+                .cloned()
+                .filter(|&mode| self.supports_session(mode))
+                .collect(),
+            features: vec![],
+        }
+    }
 }
 
 /// A trait for using an XR device
@@ -50,6 +111,13 @@ pub trait DeviceAPI: 'static {
 
     fn viewports(&self) -> Viewports;
 
+    /// The boundary of the play area, in floor coordinates with a Y coordinate of zero,
+    /// for devices which support the bounded-floor reference space.
+    /// https://immersive-web.github.io/webxr/#dom-xrboundedreferencespace-boundsgeometry
+    fn bounds_geometry(&self) -> Option<Vec<Point3D<f32, Floor>>> {
+        None
+    }
+
     /// Begin an animation frame.
     fn begin_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) -> Option<Frame>;
 
@@ -77,13 +145,31 @@ pub trait DeviceAPI: 'static {
 
     fn granted_features(&self) -> &[String];
 
-    fn request_hit_test(&mut self, _source: HitTestSource) {
-        panic!("This device does not support requesting hit tests");
+    /// Called when the registry gives the session focus or takes it away. Devices
+    /// which send input events to more than one session should stop sending them
+    /// to sessions which are not visible.
+    fn set_visibility(&mut self, _visibility: Visibility) {}
+
+    /// What the device can do for this session. Requests which the device
+    /// can't handle are rejected before they reach it.
+    fn capabilities(&self) -> DeviceCapabilities {
+        let has_floor = self.floor_transform().is_some();
+        DeviceCapabilities::new(self.granted_features().to_vec(), has_floor)
     }
 
-    fn cancel_hit_test(&mut self, _id: HitTestId) {
-        panic!("This device does not support hit tests");
+    /// Only called if the device's capabilities include hit tests
+    fn request_hit_test(&mut self, _source: HitTestSource) {}
+
+    /// Only called if the device's capabilities include hit tests
+    fn request_hit_test_for_transient_input(&mut self, _source: TransientHitTestSource) {}
+
+    fn cancel_hit_test(&mut self, _id: HitTestId) {}
+
+    fn create_anchor(&mut self, _source: AnchorSource) -> Result<AnchorId, Error> {
+        Err(Error::UnsupportedFeature("anchors".into()))
     }
+
+    fn delete_anchor(&mut self, _id: AnchorId) {}
 }
 
 impl<GL: 'static> DiscoveryAPI<GL> for Box<dyn DiscoveryAPI<GL>> {
@@ -99,4 +185,12 @@ impl<GL: 'static> DiscoveryAPI<GL> for Box<dyn DiscoveryAPI<GL>> {
     fn supports_session(&self, mode: SessionMode) -> bool {
         (&**self).supports_session(mode)
     }
+
+    fn set_device_notifier(&mut self, notifier: DeviceNotifier) {
+        (**self).set_device_notifier(notifier)
+    }
+
+    fn descriptor(&self) -> DeviceDescriptor {
+        (**self).descriptor()
+    }
 }
@@ -2,13 +2,15 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use crate::ContextId;
+use crate::LayerId;
+
+use std::fmt;
+
 #[cfg(feature = "ipc")]
 use serde::{Deserialize, Serialize};
 
 /// Errors that can be produced by XR.
-
-// TODO: this is currently incomplete!
-
 #[derive(Debug)]
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
 pub enum Error {
@@ -16,6 +18,41 @@ pub enum Error {
     CommunicationError,
     ThreadCreationError,
     InlineSession,
+    /// The device does not support the feature
     UnsupportedFeature(String),
+    /// The device supports the feature, but it was not granted to the session
+    FeatureNotGranted(String),
+    /// The device was disconnected or stopped working
+    DeviceLost,
+    /// The session has ended, so cannot be used any more
+    SessionEnded,
+    LayerNotFound(LayerId),
+    ContextNotFound(ContextId),
+    /// Creating, acquiring or presenting a swap chain image failed
+    SwapChainFailure(String),
+    /// The request is not valid in the current state, e.g. ending a frame that was never begun
+    InvalidState(String),
     BackendSpecific(String),
 }
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NoMatchingDevice => write!(fmt, "no matching XR device"),
+            Error::CommunicationError => write!(fmt, "communication with the XR device failed"),
+            Error::ThreadCreationError => write!(fmt, "could not create the XR device thread"),
+            Error::InlineSession => write!(fmt, "not supported by inline sessions"),
+            Error::UnsupportedFeature(feature) => write!(fmt, "unsupported feature {}", feature),
+            Error::FeatureNotGranted(feature) => write!(fmt, "feature {} not granted", feature),
+            Error::DeviceLost => write!(fmt, "XR device lost"),
+            Error::SessionEnded => write!(fmt, "XR session ended"),
+            Error::LayerNotFound(layer_id) => write!(fmt, "layer {:?} not found", layer_id),
+            Error::ContextNotFound(context_id) => write!(fmt, "context {:?} not found", context_id),
+            Error::SwapChainFailure(message) => write!(fmt, "swap chain failure: {}", message),
+            Error::InvalidState(message) => write!(fmt, "invalid state: {}", message),
+            Error::BackendSpecific(message) => write!(fmt, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -2,6 +2,8 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use crate::ApiSpace;
+use crate::BaseSpace;
 use crate::Frame;
 use crate::InputId;
 use crate::InputSource;
@@ -9,6 +11,8 @@ use crate::SelectEvent;
 use crate::SelectKind;
 use crate::Sender;
 
+use euclid::RigidTransform3D;
+
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub enum Event {
@@ -24,9 +28,14 @@ pub enum Event {
     VisibilityChange(Visibility),
     /// Selection started / ended
     Select(InputId, SelectKind, SelectEvent, Frame),
+    /// The origin of a reference space jumped, for example because the user recentered.
+    /// The transform is the pose of the new origin in the coordinates of the old origin,
+    /// if it is known.
+    /// https://immersive-web.github.io/webxr/#eventdef-xrreferencespace-reset
+    ReferenceSpaceReset(BaseSpace, Option<RigidTransform3D<f32, ApiSpace, ApiSpace>>),
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub enum Visibility {
     /// Session fully displayed to user
@@ -2,16 +2,24 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use crate::AnchorId;
+use crate::AnchorPose;
 use crate::Floor;
 use crate::HitTestId;
 use crate::HitTestResult;
 use crate::InputFrame;
+use crate::Mesh;
+use crate::MeshId;
 use crate::Native;
+use crate::Plane;
+use crate::PlaneId;
 use crate::SubImages;
+use crate::TransientHitTestResult;
 use crate::Viewer;
 use crate::Viewports;
 use crate::Views;
 
+use euclid::Point3D;
 use euclid::RigidTransform3D;
 
 /// The per-frame data that is provided by the device.
@@ -34,11 +42,17 @@ pub struct Frame {
     /// Value of time::precise_time_ns() when frame was obtained
     pub time_ns: u64,
 
-    /// The time the frame was sent (only set with the profile feature)
+    /// Value of time::precise_time_ns() when the session thread sent the frame
     pub sent_time: u64,
 
     /// The hit test results for this frame, if any
     pub hit_test_results: Vec<HitTestResult>,
+
+    /// The hit test results of transient input hit test sources for this frame, if any
+    pub transient_hit_test_results: Vec<TransientHitTestResult>,
+
+    /// The poses of the anchors which are tracked in this frame
+    pub anchors: Vec<AnchorPose>,
 }
 
 #[derive(Clone, Debug)]
@@ -46,7 +60,18 @@ pub struct Frame {
 pub enum FrameUpdateEvent {
     UpdateFloorTransform(Option<RigidTransform3D<f32, Native, Floor>>),
     UpdateViewports(Viewports),
+    UpdateBoundsGeometry(Option<Vec<Point3D<f32, Floor>>>),
     HitTestSourceAdded(HitTestId),
+    /// The anchor is no longer tracked, and will not be tracked again
+    AnchorLost(AnchorId),
+    /// https://immersive-web.github.io/real-world-geometry/plane-detection.html
+    PlaneAdded(Plane),
+    PlaneChanged(Plane),
+    PlaneRemoved(PlaneId),
+    /// https://immersive-web.github.io/real-world-geometry/webxrmeshing-1.html
+    MeshAdded(Mesh),
+    MeshChanged(Mesh),
+    MeshRemoved(MeshId),
 }
 
 #[derive(Clone, Debug)]
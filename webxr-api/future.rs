@@ -0,0 +1,320 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! An async facade over the registry and sessions, which works with any async runtime.
+//!
+//! Replies are still delivered over the usual channels, which are routed to their streams
+//! by the ipc router, or otherwise by a thread shared by all the streams,
+//! so the main thread registry still needs to be run as normal.
+
+use crate::DeviceEvent;
+use crate::DeviceId;
+use crate::Error;
+use crate::Event;
+use crate::Frame;
+use crate::Registry;
+use crate::Sender;
+use crate::Session;
+use crate::SessionInit;
+use crate::SessionMode;
+
+use crate::Receiver;
+
+use futures_core::Stream;
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+#[cfg(not(feature = "ipc"))]
+use std::sync::mpsc;
+#[cfg(not(feature = "ipc"))]
+use std::sync::mpsc::TryRecvError;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+#[cfg(not(feature = "ipc"))]
+use std::thread;
+#[cfg(not(feature = "ipc"))]
+use std::time::Duration;
+
+/// The messages that can be sent over our channels
+#[cfg(feature = "ipc")]
+pub trait Message: 'static + Send + serde::Serialize + for<'a> serde::Deserialize<'a> {}
+
+#[cfg(feature = "ipc")]
+impl<T> Message for T where T: 'static + Send + serde::Serialize + for<'a> serde::Deserialize<'a> {}
+
+/// The messages that can be sent over our channels
+#[cfg(not(feature = "ipc"))]
+pub trait Message: 'static + Send {}
+
+#[cfg(not(feature = "ipc"))]
+impl<T> Message for T where T: 'static + Send {}
+
+/// The messages routed to a stream from its channel
+struct Shared<T> {
+    messages: VecDeque<T>,
+    /// Whether the channel has closed, or the stream has been dropped
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl<T> Shared<T> {
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Closes a stream when the router drops its route, which it does once the channel has closed
+#[cfg(feature = "ipc")]
+struct Closer<T>(Arc<Mutex<Shared<T>>>);
+
+#[cfg(feature = "ipc")]
+impl<T> Drop for Closer<T> {
+    fn drop(&mut self) {
+        let mut shared = self.0.lock().unwrap();
+        shared.closed = true;
+        shared.wake();
+    }
+}
+
+#[cfg(feature = "ipc")]
+fn route<T: Message>(receiver: Receiver<T>, shared: Arc<Mutex<Shared<T>>>) -> Result<(), Error> {
+    let closer = Closer(shared);
+    ipc_channel::router::ROUTER.add_route(
+        receiver.to_opaque(),
+        Box::new(move |message| {
+            let mut shared = closer.0.lock().unwrap();
+            if shared.closed {
+                return;
+            }
+            match message.to() {
+                Ok(message) => shared.messages.push_back(message),
+                Err(err) => log::warn!("Failed to deserialize message: {:?}", err),
+            }
+            shared.wake();
+        }),
+    );
+    Ok(())
+}
+
+/// How often the router polls while messages are arriving, and while they are not
+#[cfg(not(feature = "ipc"))]
+const MIN_POLL_INTERVAL: Duration = Duration::from_micros(50);
+#[cfg(not(feature = "ipc"))]
+const MAX_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+#[cfg(not(feature = "ipc"))]
+enum RouteStatus {
+    Busy,
+    Idle,
+    Closed,
+}
+
+#[cfg(not(feature = "ipc"))]
+type Route = Box<dyn FnMut() -> RouteStatus + Send>;
+
+/// Without ipc there is no router thread to borrow, and std channels can't be selected on,
+/// so one thread polls the channels of all the streams, and sleeps while there are none.
+#[cfg(not(feature = "ipc"))]
+fn route<T: Message>(receiver: Receiver<T>, shared: Arc<Mutex<Shared<T>>>) -> Result<(), Error> {
+    static ROUTER: Mutex<Option<mpsc::Sender<Route>>> = Mutex::new(None);
+    let route: Route = Box::new(move || {
+        let mut shared = shared.lock().unwrap();
+        if shared.closed {
+            return RouteStatus::Closed;
+        }
+        let mut status = RouteStatus::Idle;
+        loop {
+            match receiver.try_recv() {
+                Ok(message) => {
+                    shared.messages.push_back(message);
+                    status = RouteStatus::Busy;
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    shared.closed = true;
+                    status = RouteStatus::Closed;
+                    break;
+                }
+            }
+        }
+        if !matches!(status, RouteStatus::Idle) {
+            shared.wake();
+        }
+        status
+    });
+    let mut router = ROUTER.lock().unwrap();
+    let sender = router.get_or_insert_with(|| {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || run_router(receiver));
+        sender
+    });
+    sender.send(route).or(Err(Error::CommunicationError))
+}
+
+#[cfg(not(feature = "ipc"))]
+fn run_router(new_routes: mpsc::Receiver<Route>) {
+    let mut routes: Vec<Route> = Vec::new();
+    let mut interval = MIN_POLL_INTERVAL;
+    loop {
+        if routes.is_empty() {
+            match new_routes.recv() {
+                Ok(route) => routes.push(route),
+                Err(_) => return,
+            }
+        }
+        routes.extend(new_routes.try_iter());
+        let mut busy = false;
+        routes.retain_mut(|route| match route() {
+            RouteStatus::Busy => {
+                busy = true;
+                true
+            }
+            RouteStatus::Idle => true,
+            RouteStatus::Closed => false,
+        });
+        if busy {
+            interval = MIN_POLL_INTERVAL;
+        } else {
+            thread::sleep(interval);
+            interval = (interval * 2).min(MAX_POLL_INTERVAL);
+        }
+    }
+}
+
+/// The messages received on a channel, as a stream which ends when the channel closes
+pub struct ReceiverStream<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+pub type FrameStream = ReceiverStream<Frame>;
+pub type EventStream = ReceiverStream<Event>;
+pub type DeviceEventStream = ReceiverStream<DeviceEvent>;
+
+impl<T: Message> ReceiverStream<T> {
+    /// A channel whose messages are received as a stream, without blocking a thread on it
+    pub fn channel() -> Result<(Sender<T>, ReceiverStream<T>), Error> {
+        let (sender, receiver) = crate::channel().or(Err(Error::CommunicationError))?;
+        let shared = Arc::new(Mutex::new(Shared {
+            messages: VecDeque::new(),
+            closed: false,
+            waker: None,
+        }));
+        route(receiver, shared.clone())?;
+        Ok((sender, ReceiverStream { shared }))
+    }
+
+    /// The next message, or `None` once the channel has closed
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { stream: self }
+    }
+}
+
+impl<T> Stream for ReceiverStream<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        let mut shared = self.shared.lock().unwrap();
+        if let Some(message) = shared.messages.pop_front() {
+            Poll::Ready(Some(message))
+        } else if shared.closed {
+            Poll::Ready(None)
+        } else {
+            shared.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> Drop for ReceiverStream<T> {
+    fn drop(&mut self) {
+        // The route may outlive the stream, so stop it buffering messages
+        let mut shared = self.shared.lock().unwrap();
+        shared.closed = true;
+        shared.messages.clear();
+    }
+}
+
+/// The future returned by `ReceiverStream::recv`
+pub struct Recv<'a, T> {
+    stream: &'a mut ReceiverStream<T>,
+}
+
+impl<'a, T> Future for Recv<'a, T> {
+    type Output = Option<T>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        Pin::new(&mut *self.stream).poll_next(cx)
+    }
+}
+
+/// A registry whose requests return futures rather than replying on a channel
+#[derive(Clone)]
+pub struct AsyncRegistry {
+    registry: Registry,
+}
+
+impl AsyncRegistry {
+    pub fn new(registry: Registry) -> AsyncRegistry {
+        AsyncRegistry { registry }
+    }
+
+    pub async fn supports_session(&mut self, mode: SessionMode) -> Result<(), Error> {
+        let (sender, replies) = ReceiverStream::channel()?;
+        self.registry.supports_session(mode, sender);
+        reply(replies).await
+    }
+
+    /// Request a session, returning it along with the stream of its animation frames
+    pub async fn request_session(
+        &mut self,
+        mode: SessionMode,
+        init: SessionInit,
+    ) -> Result<(Session, FrameStream), Error> {
+        let (sender, replies) = ReceiverStream::channel()?;
+        let (frame_sender, frames) = ReceiverStream::channel()?;
+        self.registry
+            .request_session(mode, init, sender, frame_sender);
+        let session = reply(replies).await?;
+        Ok((session, frames))
+    }
+
+    /// The stream of devices being connected, disconnected or changed
+    pub fn device_events(&mut self) -> Result<DeviceEventStream, Error> {
+        let (sender, events) = ReceiverStream::channel()?;
+        self.registry.add_device_event_dest(sender);
+        Ok(events)
+    }
+
+    /// Request a session from a particular device
+    pub async fn request_session_on_device(
+        &mut self,
+        device: DeviceId,
+        mode: SessionMode,
+        init: SessionInit,
+    ) -> Result<(Session, FrameStream), Error> {
+        let (sender, replies) = ReceiverStream::channel()?;
+        let (frame_sender, frames) = ReceiverStream::channel()?;
+        self.registry
+            .request_session_on_device(device, mode, init, sender, frame_sender);
+        let session = reply(replies).await?;
+        Ok((session, frames))
+    }
+}
+
+/// The stream of a session's events. This replaces any previous event destination.
+pub fn session_events(session: &mut Session) -> Result<EventStream, Error> {
+    let (sender, events) = ReceiverStream::channel()?;
+    session.set_event_dest(sender);
+    Ok(events)
+}
+
+async fn reply<T: Message>(mut replies: ReceiverStream<Result<T, Error>>) -> Result<T, Error> {
+    replies
+        .recv()
+        .await
+        .unwrap_or(Err(Error::CommunicationError))
+}
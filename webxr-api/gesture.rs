@@ -0,0 +1,161 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Recognizing select and squeeze gestures from hand joints, for backends
+//! which track hands but do not report their own select and squeeze actions.
+//!
+//! A pinch of the thumb and index finger tips is a select, and curling
+//! the middle, ring and little fingers into a grab is a squeeze.
+
+use crate::Finger;
+use crate::FingerJoint;
+use crate::Hand;
+use crate::Joint;
+use crate::JointFrame;
+use crate::SelectEvent;
+use crate::SelectKind;
+
+#[cfg(feature = "ipc")]
+use serde::{Deserialize, Serialize};
+
+/// The thresholds at which gestures start and end. Each gesture ends at
+/// a different threshold to the one it starts at, so that noisy joint data
+/// does not produce a flurry of events.
+#[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct GestureThresholds {
+    /// The gap between the thumb and index finger tips at which a pinch starts, in meters
+    pub pinch_start: f32,
+    /// The gap between the thumb and index finger tips at which a pinch ends, in meters
+    pub pinch_end: f32,
+    /// How extended the fingers are when a grab starts, from 0 (curled) to 1 (straight)
+    pub grab_start: f32,
+    /// How extended the fingers are when a grab ends, from 0 (curled) to 1 (straight)
+    pub grab_end: f32,
+}
+
+impl Default for GestureThresholds {
+    fn default() -> GestureThresholds {
+        GestureThresholds {
+            pinch_start: 0.01,
+            pinch_end: 0.03,
+            grab_start: 0.35,
+            grab_end: 0.5,
+        }
+    }
+}
+
+/// Turns successive hands into select and squeeze events
+#[derive(Clone, Debug, Default)]
+pub struct GestureRecognizer {
+    thresholds: GestureThresholds,
+    selecting: bool,
+    squeezing: bool,
+}
+
+impl GestureRecognizer {
+    pub fn new(thresholds: GestureThresholds) -> GestureRecognizer {
+        GestureRecognizer {
+            thresholds,
+            selecting: false,
+            squeezing: false,
+        }
+    }
+
+    /// Whether a select is in progress
+    pub fn selecting(&self) -> bool {
+        self.selecting
+    }
+
+    /// Whether a squeeze is in progress
+    pub fn squeezing(&self) -> bool {
+        self.squeezing
+    }
+
+    /// Update the recognizer with the latest hand, or `None` if the hand
+    /// is no longer tracked, returning any gestures which started or ended.
+    /// Gestures in progress when tracking is lost are ended without completing.
+    pub fn update(&mut self, hand: Option<&Hand<JointFrame>>) -> Vec<(SelectKind, SelectEvent)> {
+        let thresholds = self.thresholds;
+        let mut events = Vec::new();
+        let pinch = hand.and_then(pinch_gap);
+        let select = transition(
+            &mut self.selecting,
+            pinch.map(|gap| gap < thresholds.pinch_start),
+            pinch.map(|gap| gap > thresholds.pinch_end),
+        );
+        if let Some(event) = select {
+            events.push((SelectKind::Select, event));
+        }
+        let grab = hand.and_then(extension);
+        let squeeze = transition(
+            &mut self.squeezing,
+            grab.map(|extension| extension < thresholds.grab_start),
+            grab.map(|extension| extension > thresholds.grab_end),
+        );
+        if let Some(event) = squeeze {
+            events.push((SelectKind::Squeeze, event));
+        }
+        events
+    }
+}
+
+/// Update whether a gesture is active, given whether it should start or end,
+/// which are `None` if the gesture can't be recognized.
+fn transition(active: &mut bool, start: Option<bool>, end: Option<bool>) -> Option<SelectEvent> {
+    match (*active, start, end) {
+        (false, Some(true), _) => {
+            *active = true;
+            Some(SelectEvent::Start)
+        }
+        (true, _, Some(true)) => {
+            *active = false;
+            Some(SelectEvent::Select)
+        }
+        (true, None, _) => {
+            // We lost tracking, so cancel the gesture
+            *active = false;
+            Some(SelectEvent::End)
+        }
+        _ => None,
+    }
+}
+
+/// The gap between the surfaces of the thumb and index finger tips
+fn pinch_gap(hand: &Hand<JointFrame>) -> Option<f32> {
+    let thumb = hand.get(Joint::ThumbPhalanxTip)?;
+    let index = hand.get(Joint::Index(FingerJoint::PhalanxTip))?;
+    let distance = (thumb.pose.translation - index.pose.translation).length();
+    Some(distance - thumb.radius - index.radius)
+}
+
+/// How extended the middle, ring and little fingers are, from 0 (curled) to 1 (straight)
+fn extension(hand: &Hand<JointFrame>) -> Option<f32> {
+    let fingers = [&hand.middle, &hand.ring, &hand.little];
+    let mut total = 0.0;
+    for finger in &fingers {
+        total += finger_extension(finger)?;
+    }
+    Some(total / fingers.len() as f32)
+}
+
+/// How far the tip of a finger is from the base of the palm, relative to the length of the finger
+fn finger_extension(finger: &Finger<JointFrame>) -> Option<f32> {
+    let joints = [
+        finger.get(FingerJoint::Metacarpal)?,
+        finger.get(FingerJoint::PhalanxProximal)?,
+        finger.get(FingerJoint::PhalanxIntermediate)?,
+        finger.get(FingerJoint::PhalanxDistal)?,
+        finger.get(FingerJoint::PhalanxTip)?,
+    ];
+    let length: f32 = joints
+        .windows(2)
+        .map(|bone| (bone[1].pose.translation - bone[0].pose.translation).length())
+        .sum();
+    if length <= 0.0 {
+        return None;
+    }
+    let reach = (joints[4].pose.translation - joints[0].pose.translation).length();
+    Some(reach / length)
+}
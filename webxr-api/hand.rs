@@ -73,6 +73,21 @@ impl<J> Hand<J> {
             Joint::Little(f) => self.little.get(f),
         }
     }
+
+    /// The slot for a joint, which can be used to set or clear it
+    pub fn get_mut(&mut self, joint: Joint) -> &mut Option<J> {
+        match joint {
+            Joint::Wrist => &mut self.wrist,
+            Joint::ThumbMetacarpal => &mut self.thumb_metacarpal,
+            Joint::ThumbPhalanxProximal => &mut self.thumb_phalanx_proximal,
+            Joint::ThumbPhalanxDistal => &mut self.thumb_phalanx_distal,
+            Joint::ThumbPhalanxTip => &mut self.thumb_phalanx_tip,
+            Joint::Index(f) => self.index.get_mut(f),
+            Joint::Middle(f) => self.middle.get_mut(f),
+            Joint::Ring(f) => self.ring.get_mut(f),
+            Joint::Little(f) => self.little.get_mut(f),
+        }
+    }
 }
 
 impl<J> Finger<J> {
@@ -95,6 +110,17 @@ impl<J> Finger<J> {
             FingerJoint::PhalanxTip => self.phalanx_tip.as_ref(),
         }
     }
+
+    /// The slot for a joint, which can be used to set or clear it
+    pub fn get_mut(&mut self, joint: FingerJoint) -> &mut Option<J> {
+        match joint {
+            FingerJoint::Metacarpal => &mut self.metacarpal,
+            FingerJoint::PhalanxProximal => &mut self.phalanx_proximal,
+            FingerJoint::PhalanxIntermediate => &mut self.phalanx_intermediate,
+            FingerJoint::PhalanxDistal => &mut self.phalanx_distal,
+            FingerJoint::PhalanxTip => &mut self.phalanx_tip,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -0,0 +1,335 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Ready-made hand poses, for simulating articulated hands.
+//!
+//! Poses are laid out in the space of the wrist joint, using the
+//! same conventions as joint poses: the fingers point along -Z,
+//! and +Y points out of the back of the hand. Lengths are in meters.
+
+use crate::Finger;
+use crate::Hand;
+use crate::HandSpace;
+use crate::Handedness;
+use crate::Joint;
+use crate::JointFrame;
+use crate::Native;
+
+use euclid::Angle;
+use euclid::RigidTransform3D;
+use euclid::Rotation3D;
+use euclid::Vector3D;
+
+#[cfg(feature = "ipc")]
+use serde::{Deserialize, Serialize};
+
+type Position = Vector3D<f32, HandSpace>;
+type Orientation = Rotation3D<f32, HandSpace, HandSpace>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub enum HandPose {
+    /// All fingers extended, with the thumb spread
+    OpenPalm,
+    /// All fingers curled, with the thumb across them
+    Fist,
+    /// The index finger extended, and the other fingers curled
+    Point,
+    /// The thumb tip touching the index finger tip
+    Pinch,
+    /// A fist, with the thumb extended
+    ThumbsUp,
+}
+
+/// The shape of a finger of a right hand, in the space of the wrist
+struct FingerShape {
+    /// The position of the metacarpal joint
+    base: [f32; 3],
+    /// The angle of the metacarpal towards the thumb, in radians
+    splay: f32,
+    /// The lengths of the metacarpal, proximal, intermediate and distal bones
+    lengths: [f32; 4],
+    /// The radii of the metacarpal, proximal, intermediate, distal and tip joints
+    radii: [f32; 5],
+}
+
+const INDEX: FingerShape = FingerShape {
+    base: [-0.020, 0.0, -0.010],
+    splay: 0.08,
+    lengths: [0.065, 0.040, 0.024, 0.022],
+    radii: [0.021, 0.011, 0.009, 0.008, 0.0075],
+};
+
+const MIDDLE: FingerShape = FingerShape {
+    base: [-0.005, 0.0, -0.010],
+    splay: 0.0,
+    lengths: [0.063, 0.045, 0.028, 0.024],
+    radii: [0.021, 0.011, 0.009, 0.008, 0.0075],
+};
+
+const RING: FingerShape = FingerShape {
+    base: [0.010, 0.0, -0.010],
+    splay: -0.06,
+    lengths: [0.058, 0.042, 0.027, 0.023],
+    radii: [0.020, 0.010, 0.0085, 0.0075, 0.007],
+};
+
+const LITTLE: FingerShape = FingerShape {
+    base: [0.023, 0.0, -0.010],
+    splay: -0.12,
+    lengths: [0.053, 0.033, 0.019, 0.020],
+    radii: [0.018, 0.009, 0.0075, 0.0065, 0.006],
+};
+
+/// The position of the thumb metacarpal joint of a right hand
+const THUMB_BASE: [f32; 3] = [-0.025, -0.015, -0.025];
+
+/// The lengths of the thumb metacarpal, proximal and distal bones
+const THUMB_LENGTHS: [f32; 3] = [0.040, 0.032, 0.027];
+
+/// The radii of the thumb metacarpal, proximal, distal and tip joints
+const THUMB_RADII: [f32; 4] = [0.019, 0.013, 0.011, 0.009];
+
+const WRIST_RADIUS: f32 = 0.025;
+
+/// How far each finger is flexed at its proximal, intermediate and distal joints, in radians
+const EXTENDED: [f32; 3] = [0.0, 0.0, 0.0];
+const CURLED: [f32; 3] = [1.45, 1.6, 0.8];
+const RELAXED: [f32; 3] = [0.9, 1.0, 0.6];
+const PINCHING: [f32; 3] = [0.6, 0.7, 0.4];
+
+impl HandPose {
+    /// The joints of this pose, for a hand whose wrist joint has the given pose.
+    /// Hands with no handedness are given the joints of a right hand.
+    pub fn hand(
+        self,
+        handedness: Handedness,
+        wrist: RigidTransform3D<f32, HandSpace, Native>,
+    ) -> Hand<JointFrame> {
+        let (index, middle, ring, little) = match self {
+            HandPose::OpenPalm => (EXTENDED, EXTENDED, EXTENDED, EXTENDED),
+            HandPose::Fist | HandPose::ThumbsUp => (CURLED, CURLED, CURLED, CURLED),
+            HandPose::Point => (EXTENDED, CURLED, CURLED, CURLED),
+            HandPose::Pinch => (PINCHING, RELAXED, RELAXED, RELAXED),
+        };
+        let index = finger(&INDEX, index);
+        let middle = finger(&MIDDLE, middle);
+        let ring = finger(&RING, ring);
+        let little = finger(&LITTLE, little);
+
+        let thumb_base = vec(THUMB_BASE);
+        let thumb_reach = THUMB_LENGTHS.iter().sum::<f32>();
+        let thumb_target = match self {
+            HandPose::OpenPalm => thumb_base + vec([-0.8, 0.05, -0.6]).normalize() * thumb_reach,
+            HandPose::ThumbsUp => thumb_base + vec([-0.95, 0.1, -0.3]).normalize() * thumb_reach,
+            HandPose::Fist | HandPose::Point => {
+                // Across the middle phalanges of the curled fingers
+                let middle_joint = middle.phalanx_intermediate.unwrap().0;
+                middle_joint + vec([0.0, -0.01, -0.015])
+            }
+            HandPose::Pinch => {
+                // Touching the index finger tip
+                let (index_tip, _, index_radius) = index.phalanx_tip.unwrap();
+                let towards_thumb = (thumb_base - index_tip).normalize();
+                index_tip + towards_thumb * (index_radius + THUMB_RADII[3])
+            }
+        };
+        let thumb = thumb(thumb_target);
+
+        let right = Hand {
+            wrist: Some((Position::zero(), Orientation::identity(), WRIST_RADIUS)),
+            thumb_metacarpal: Some(thumb[0]),
+            thumb_phalanx_proximal: Some(thumb[1]),
+            thumb_phalanx_distal: Some(thumb[2]),
+            thumb_phalanx_tip: Some(thumb[3]),
+            index,
+            middle,
+            ring,
+            little,
+        };
+        let mirror = match handedness {
+            Handedness::Left => true,
+            Handedness::Right | Handedness::None => false,
+        };
+        right.map(|joint, _| {
+            let (position, orientation, radius) = (*joint)?;
+            let (position, orientation) = if mirror {
+                // Reflect in the YZ plane
+                let position = Position::new(-position.x, position.y, position.z);
+                let orientation = Orientation::quaternion(
+                    orientation.i,
+                    -orientation.j,
+                    -orientation.k,
+                    orientation.r,
+                );
+                (position, orientation)
+            } else {
+                (position, orientation)
+            };
+            let local = RigidTransform3D::new(orientation, position);
+            Some(JointFrame {
+                pose: local.post_transform(&wrist),
+                radius,
+            })
+        })
+    }
+}
+
+fn vec(v: [f32; 3]) -> Position {
+    Position::new(v[0], v[1], v[2])
+}
+
+/// A rotation which bends a joint towards the palm
+fn flex(angle: f32) -> Orientation {
+    Orientation::around_x(Angle::radians(-angle))
+}
+
+/// Lay out a finger of a right hand by flexing each of its joints
+fn finger(shape: &FingerShape, flexion: [f32; 3]) -> Finger<(Position, Orientation, f32)> {
+    let mut position = vec(shape.base);
+    let mut orientation = Orientation::around_y(Angle::radians(shape.splay));
+    let mut joints = [(position, orientation, 0.0); 5];
+    for i in 0..5 {
+        if 0 < i && i < 4 {
+            orientation = flex(flexion[i - 1]).post_rotate(&orientation);
+        }
+        joints[i] = (position, orientation, shape.radii[i]);
+        if i < 4 {
+            position += orientation.transform_vector3d(Position::new(0.0, 0.0, -shape.lengths[i]));
+        }
+    }
+    Finger {
+        metacarpal: Some(joints[0]),
+        phalanx_proximal: Some(joints[1]),
+        phalanx_intermediate: Some(joints[2]),
+        phalanx_distal: Some(joints[3]),
+        phalanx_tip: Some(joints[4]),
+    }
+}
+
+/// Lay out the thumb of a right hand so that its tip reaches the target
+/// (or as close to it as it can). The metacarpal bends away from the palm,
+/// and the phalanges are kept straight.
+fn thumb(target: Position) -> [(Position, Orientation, f32); 4] {
+    let base = vec(THUMB_BASE);
+    let metacarpal = THUMB_LENGTHS[0];
+    let phalanges = THUMB_LENGTHS[1] + THUMB_LENGTHS[2];
+    let to_target = target - base;
+    let distance = to_target
+        .length()
+        .clamp((metacarpal - phalanges).abs(), metacarpal + phalanges);
+    let direction = to_target.normalize();
+
+    // The angle between the metacarpal and the direction to the target
+    let cos = (metacarpal * metacarpal + distance * distance - phalanges * phalanges)
+        / (2.0 * metacarpal * distance);
+    let angle = cos.clamp(-1.0, 1.0).acos();
+    let outwards = vec([-1.0, 0.3, 0.0]);
+    let outwards = (outwards - direction * outwards.dot(direction)).normalize();
+    let knuckle = base + (direction * angle.cos() + outwards * angle.sin()) * metacarpal;
+    let tip = base + direction * distance;
+    let phalanx = (tip - knuckle).normalize();
+    let distal = knuckle + phalanx * THUMB_LENGTHS[1];
+
+    let metacarpal_orientation = look_along(knuckle - base);
+    let phalanx_orientation = look_along(phalanx);
+    [
+        (base, metacarpal_orientation, THUMB_RADII[0]),
+        (knuckle, phalanx_orientation, THUMB_RADII[1]),
+        (distal, phalanx_orientation, THUMB_RADII[2]),
+        (tip, phalanx_orientation, THUMB_RADII[3]),
+    ]
+}
+
+/// The orientation whose -Z axis points along the given direction,
+/// with its +Y axis as close as possible to the back of the hand.
+fn look_along(direction: Position) -> Orientation {
+    let z = -direction.normalize();
+    let up = if z.y.abs() < 0.99 {
+        vec([0.0, 1.0, 0.0])
+    } else {
+        vec([0.0, 0.0, 1.0])
+    };
+    let x = up.cross(z).normalize();
+    let y = z.cross(x);
+    // Convert the rotation matrix whose columns are x, y and z to a quaternion
+    let trace = x.x + y.y + z.z;
+    let (i, j, k, r) = if trace > 0.0 {
+        let s = (trace + 1.0).sqrt() * 2.0;
+        ((y.z - z.y) / s, (z.x - x.z) / s, (x.y - y.x) / s, s / 4.0)
+    } else if x.x > y.y && x.x > z.z {
+        let s = (1.0 + x.x - y.y - z.z).sqrt() * 2.0;
+        (s / 4.0, (y.x + x.y) / s, (z.x + x.z) / s, (y.z - z.y) / s)
+    } else if y.y > z.z {
+        let s = (1.0 + y.y - x.x - z.z).sqrt() * 2.0;
+        ((y.x + x.y) / s, s / 4.0, (z.y + y.z) / s, (z.x - x.z) / s)
+    } else {
+        let s = (1.0 + z.z - x.x - y.y).sqrt() * 2.0;
+        ((z.x + x.z) / s, (z.y + y.z) / s, s / 4.0, (x.y - y.x) / s)
+    };
+    Orientation::unit_quaternion(i, j, k, r)
+}
+
+/// Interpolate between two hands, where `t` goes from 0 (`from`) to 1 (`to`).
+/// Joints which are only tracked in one of the hands jump halfway through.
+pub fn interpolate_hand(
+    from: &Hand<JointFrame>,
+    to: &Hand<JointFrame>,
+    t: f32,
+) -> Hand<JointFrame> {
+    from.map(
+        |from_joint, joint: Joint| match (from_joint, to.get(joint)) {
+            (Some(a), Some(b)) => Some(JointFrame {
+                pose: RigidTransform3D::new(
+                    a.pose.rotation.slerp(&b.pose.rotation, t),
+                    a.pose.translation.lerp(b.pose.translation, t),
+                ),
+                radius: a.radius + (b.radius - a.radius) * t,
+            }),
+            (Some(a), None) if t < 0.5 => Some(*a),
+            (None, Some(b)) if t >= 0.5 => Some(*b),
+            _ => None,
+        },
+    )
+}
+
+/// An iterator over the hands of a transition between two hands,
+/// spread over a number of frames. The last hand is the target hand.
+pub struct HandTransition {
+    from: Hand<JointFrame>,
+    to: Hand<JointFrame>,
+    frames: u32,
+    frame: u32,
+}
+
+impl HandTransition {
+    pub fn new(from: Hand<JointFrame>, to: Hand<JointFrame>, frames: u32) -> HandTransition {
+        HandTransition {
+            from,
+            to,
+            frames,
+            frame: 0,
+        }
+    }
+}
+
+impl Iterator for HandTransition {
+    type Item = Hand<JointFrame>;
+
+    fn next(&mut self) -> Option<Hand<JointFrame>> {
+        if self.frame >= self.frames {
+            return None;
+        }
+        self.frame += 1;
+        let t = self.frame as f32 / self.frames as f32;
+        Some(interpolate_hand(&self.from, &self.to, t))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.frames - self.frame) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for HandTransition {}
@@ -1,4 +1,5 @@
 use crate::ApiSpace;
+use crate::InputId;
 use crate::Native;
 use crate::Space;
 use euclid::Point3D;
@@ -37,6 +38,18 @@ pub struct HitTestSource {
     pub types: EntityTypes,
 }
 
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// https://immersive-web.github.io/hit-test/#dictdef-xrtransientinputhittestoptionsinit
+pub struct TransientHitTestSource {
+    pub id: HitTestId,
+    /// The input profile of the transient inputs to hit test from
+    pub profile: String,
+    /// The ray, in the target ray space of each input
+    pub ray: Ray<ApiSpace>,
+    pub types: EntityTypes,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub struct HitTestId(pub u32);
@@ -57,6 +70,16 @@ pub struct HitTestResult {
     pub space: RigidTransform3D<f32, HitTestSpace, Native>,
 }
 
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// The results of a transient input hit test source for one of its inputs
+/// https://immersive-web.github.io/hit-test/#xrtransientinputhittestresult-interface
+pub struct TransientHitTestResult {
+    pub id: HitTestId,
+    pub input: InputId,
+    pub results: Vec<HitTestResult>,
+}
+
 #[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 /// The coordinate space of a hit test result
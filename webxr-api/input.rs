@@ -49,6 +49,76 @@ pub struct InputFrame {
     pub pressed: bool,
     pub hand: Option<Box<Hand<JointFrame>>>,
     pub squeezed: bool,
+    pub gamepad: Option<Gamepad>,
+}
+
+/// https://immersive-web.github.io/webxr-gamepads-module/#xr-standard-gamepad-mapping
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub enum GamepadMapping {
+    /// No mapping, which is reported as the empty string
+    None,
+    XrStandard,
+}
+
+/// https://w3c.github.io/gamepad/#dom-gamepadbutton
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct GamepadButton {
+    pub pressed: bool,
+    pub touched: bool,
+    /// How far the button is pressed, from 0 to 1
+    pub value: f32,
+}
+
+/// https://w3c.github.io/gamepad/#dom-gamepad
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct Gamepad {
+    pub mapping: GamepadMapping,
+    pub buttons: Vec<GamepadButton>,
+    /// Axis values from -1 to 1, where right is positive
+    /// for the X axes, and up is negative for the Y axes
+    pub axes: Vec<f32>,
+}
+
+impl Gamepad {
+    /// The indices of the buttons in the xr-standard mapping
+    pub const TRIGGER: usize = 0;
+    pub const SQUEEZE: usize = 1;
+    pub const TOUCHPAD: usize = 2;
+    pub const THUMBSTICK: usize = 3;
+
+    /// The indices of the axes in the xr-standard mapping
+    pub const TOUCHPAD_X: usize = 0;
+    pub const TOUCHPAD_Y: usize = 1;
+    pub const THUMBSTICK_X: usize = 2;
+    pub const THUMBSTICK_Y: usize = 3;
+
+    /// A gamepad with the xr-standard mapping, with all its buttons and axes at rest
+    pub fn xr_standard() -> Gamepad {
+        Gamepad {
+            mapping: GamepadMapping::XrStandard,
+            buttons: vec![GamepadButton::default(); 4],
+            axes: vec![0.0; 4],
+        }
+    }
+
+    /// Set a button, adding buttons if there are not enough of them
+    pub fn set_button(&mut self, index: usize, button: GamepadButton) {
+        if self.buttons.len() <= index {
+            self.buttons.resize(index + 1, GamepadButton::default());
+        }
+        self.buttons[index] = button;
+    }
+
+    /// Set an axis, adding axes if there are not enough of them
+    pub fn set_axis(&mut self, index: usize, value: f32) {
+        if self.axes.len() <= index {
+            self.axes.resize(index + 1, 0.0);
+        }
+        self.axes[index] = value;
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -3,11 +3,14 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
 use crate::Error;
+use crate::Space;
 use crate::Viewport;
 use crate::Viewports;
 
+use euclid::Point2D;
 use euclid::Rect;
 use euclid::Size2D;
+use euclid::Vector2D;
 
 use std::fmt::Debug;
 use std::sync::atomic::AtomicUsize;
@@ -118,6 +121,17 @@ pub trait LayerManagerAPI<GL: GLTypes> {
         contexts: &mut dyn GLContexts<GL>,
         layers: &[(ContextId, LayerId)],
     ) -> Result<(), Error>;
+
+    /// Called between frames when the device's viewports change, so that layers
+    /// which are split into the device's viewports can be reallocated before the next frame.
+    fn set_viewports(
+        &mut self,
+        _device: &mut GL::Device,
+        _contexts: &mut dyn GLContexts<GL>,
+        _viewports: Viewports,
+    ) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 pub struct LayerManager(Box<dyn Send + LayerManagerAPI<()>>);
@@ -151,6 +165,10 @@ impl LayerManager {
     pub fn end_frame(&mut self, layers: &[(ContextId, LayerId)]) -> Result<(), Error> {
         self.0.end_frame(&mut (), &mut (), layers)
     }
+
+    pub fn set_viewports(&mut self, viewports: Viewports) -> Result<(), Error> {
+        self.0.set_viewports(&mut (), &mut (), viewports)
+    }
 }
 
 impl LayerManager {
@@ -237,11 +255,214 @@ pub enum LayerInit {
         stencil: bool,
         alpha: bool,
         scale_factor: f32,
+        /// Either the default layout, whose views are the device's viewports,
+        /// or a texture array with one layer for each of the device's views
+        layout: LayerLayout,
+    },
+    // https://immersive-web.github.io/layers/#xrquadlayerinittype
+    QuadLayer {
+        /// The space, offset by the quad's transform, whose origin is the center of the quad
+        space: Space,
+        /// The size of the quad in metres
+        width: f32,
+        height: f32,
+        /// The size of the texture for each view
+        view_pixel_width: i32,
+        view_pixel_height: i32,
+        layout: LayerLayout,
+        depth: bool,
+        stencil: bool,
+        alpha: bool,
+    },
+    // https://immersive-web.github.io/layers/#xrcylinderlayerinittype
+    CylinderLayer {
+        /// The space, offset by the cylinder's transform, whose origin is the center of the cylinder
+        space: Space,
+        /// The radius of the cylinder in metres
+        radius: f32,
+        /// The angle in radians of the part of the cylinder which is visible,
+        /// centered on the negative Z axis
+        central_angle: f32,
+        /// The ratio of the width of the visible part of the cylinder to its height
+        aspect_ratio: f32,
+        /// The size of the texture for each view
+        view_pixel_width: i32,
+        view_pixel_height: i32,
+        layout: LayerLayout,
+        depth: bool,
+        stencil: bool,
+        alpha: bool,
+    },
+    // https://immersive-web.github.io/layers/#xrequirectlayerinittype
+    EquirectLayer {
+        /// The space, offset by the sphere's transform, whose origin is the center of the sphere
+        space: Space,
+        /// The radius of the sphere in metres, or zero for a sphere which is infinitely far away
+        radius: f32,
+        /// The angles in radians of the part of the sphere which is visible,
+        /// centered on the negative Z axis
+        central_horizontal_angle: f32,
+        upper_vertical_angle: f32,
+        lower_vertical_angle: f32,
+        /// The size of the texture for each view
+        view_pixel_width: i32,
+        view_pixel_height: i32,
+        layout: LayerLayout,
+        depth: bool,
+        stencil: bool,
+        alpha: bool,
+    },
+    // https://immersive-web.github.io/layers/#xrcubelayerinittype
+    CubeLayer {
+        /// The space, offset by the cube's orientation, whose orientation is the cube's.
+        /// The cube is centered on the viewer, and is infinitely far away.
+        space: Space,
+        /// The size of the texture for each face of the cube. The faces of each view are
+        /// laid out in a three by two grid, in the order +X, -X, +Y, -Y, +Z, -Z starting
+        /// from the bottom left, and each face is rendered looking out from the center of
+        /// the cube, with +Y up, or +Z up when looking along +Y, or -Z up when looking along -Y.
+        view_pixel_width: i32,
+        view_pixel_height: i32,
+        layout: LayerLayout,
+        /// https://immersive-web.github.io/layers/#dom-xrlayerinit-isstatic
+        /// Static layers are only drawn once, in the first frame after they are created.
+        is_static: bool,
+        depth: bool,
+        stencil: bool,
+        alpha: bool,
     },
-    // TODO: other layer types
+}
+
+/// The kinds of layer, so that devices can say which ones they support
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
+pub enum LayerType {
+    WebGL,
+    Projection,
+    Quad,
+    Cylinder,
+    Equirect,
+    Cube,
 }
 
 impl LayerInit {
+    pub fn layer_type(&self) -> LayerType {
+        match self {
+            LayerInit::WebGLLayer { .. } => LayerType::WebGL,
+            LayerInit::ProjectionLayer { .. } => LayerType::Projection,
+            LayerInit::QuadLayer { .. } => LayerType::Quad,
+            LayerInit::CylinderLayer { .. } => LayerType::Cylinder,
+            LayerInit::EquirectLayer { .. } => LayerType::Equirect,
+            LayerInit::CubeLayer { .. } => LayerType::Cube,
+        }
+    }
+
+    /// The space the layer is placed in, for layers which aren't rendered from the viewer
+    pub fn space(&self) -> Option<Space> {
+        match self {
+            LayerInit::WebGLLayer { .. } | LayerInit::ProjectionLayer { .. } => None,
+            LayerInit::QuadLayer { space, .. }
+            | LayerInit::CylinderLayer { space, .. }
+            | LayerInit::EquirectLayer { space, .. }
+            | LayerInit::CubeLayer { space, .. } => Some(*space),
+        }
+    }
+
+    /// Whether the layer is drawn once, rather than every frame
+    pub fn is_static(&self) -> bool {
+        match self {
+            LayerInit::CubeLayer { is_static, .. } => *is_static,
+            LayerInit::WebGLLayer { .. }
+            | LayerInit::ProjectionLayer { .. }
+            | LayerInit::QuadLayer { .. }
+            | LayerInit::CylinderLayer { .. }
+            | LayerInit::EquirectLayer { .. } => false,
+        }
+    }
+
+    pub fn layout(&self) -> LayerLayout {
+        match self {
+            LayerInit::WebGLLayer { .. } => LayerLayout::Default,
+            LayerInit::ProjectionLayer { layout, .. }
+            | LayerInit::QuadLayer { layout, .. }
+            | LayerInit::CylinderLayer { layout, .. }
+            | LayerInit::EquirectLayer { layout, .. }
+            | LayerInit::CubeLayer { layout, .. } => *layout,
+        }
+    }
+
+    pub fn alpha(&self) -> bool {
+        match self {
+            LayerInit::WebGLLayer { alpha, .. }
+            | LayerInit::ProjectionLayer { alpha, .. }
+            | LayerInit::QuadLayer { alpha, .. }
+            | LayerInit::CylinderLayer { alpha, .. }
+            | LayerInit::EquirectLayer { alpha, .. }
+            | LayerInit::CubeLayer { alpha, .. } => *alpha,
+        }
+    }
+
+    pub fn has_depth_stencil(&self) -> bool {
+        // TODO: Treat depth and stencil separately?
+        match self {
+            LayerInit::WebGLLayer { stencil, depth, .. }
+            | LayerInit::ProjectionLayer { stencil, depth, .. }
+            | LayerInit::QuadLayer { stencil, depth, .. }
+            | LayerInit::CylinderLayer { stencil, depth, .. }
+            | LayerInit::EquirectLayer { stencil, depth, .. }
+            | LayerInit::CubeLayer { stencil, depth, .. } => *stencil | *depth,
+        }
+    }
+
+    /// The viewports of the views in the layer's texture, if they are not the device's.
+    /// Cube layers have six views for each eye, one for each face.
+    pub fn view_viewports(&self) -> Option<Vec<Rect<i32, Viewport>>> {
+        match self {
+            LayerInit::WebGLLayer { .. } | LayerInit::ProjectionLayer { .. } => None,
+            LayerInit::QuadLayer {
+                view_pixel_width,
+                view_pixel_height,
+                layout,
+                ..
+            }
+            | LayerInit::CylinderLayer {
+                view_pixel_width,
+                view_pixel_height,
+                layout,
+                ..
+            }
+            | LayerInit::EquirectLayer {
+                view_pixel_width,
+                view_pixel_height,
+                layout,
+                ..
+            } => Some(layout.view_viewports(Size2D::new(*view_pixel_width, *view_pixel_height))),
+            LayerInit::CubeLayer {
+                view_pixel_width,
+                view_pixel_height,
+                layout,
+                ..
+            } => {
+                let face_size = Size2D::new(*view_pixel_width, *view_pixel_height);
+                let grid_size = Size2D::new(face_size.width * 3, face_size.height * 2);
+                let faces = layout
+                    .view_viewports(grid_size)
+                    .into_iter()
+                    .flat_map(|grid| {
+                        (0..6).map(move |face| {
+                            let column = face % 3;
+                            let row = face / 3;
+                            let offset =
+                                Vector2D::new(face_size.width * column, face_size.height * row);
+                            Rect::new(grid.origin + offset, face_size)
+                        })
+                    })
+                    .collect();
+                Some(faces)
+            }
+        }
+    }
+
     pub fn texture_size(&self, viewports: &Viewports) -> Size2D<i32, Viewport> {
         match self {
             LayerInit::WebGLLayer {
@@ -259,21 +480,83 @@ impl LayerInit {
                     .size;
                 (native_size.to_f32() * *scale).to_i32()
             }
+            LayerInit::QuadLayer {
+                view_pixel_width,
+                view_pixel_height,
+                layout,
+                ..
+            }
+            | LayerInit::CylinderLayer {
+                view_pixel_width,
+                view_pixel_height,
+                layout,
+                ..
+            }
+            | LayerInit::EquirectLayer {
+                view_pixel_width,
+                view_pixel_height,
+                layout,
+                ..
+            } => layout.texture_size(Size2D::new(*view_pixel_width, *view_pixel_height)),
+            LayerInit::CubeLayer {
+                view_pixel_width,
+                view_pixel_height,
+                layout,
+                ..
+            } => layout.texture_size(Size2D::new(view_pixel_width * 3, view_pixel_height * 2)),
         }
     }
 }
 
 /// https://immersive-web.github.io/layers/#enumdef-xrlayerlayout
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "ipc", derive(Deserialize, Serialize))]
 pub enum LayerLayout {
-    // TODO: Default
+    // Allocates one texture, which is split into the device's viewports.
+    // Only WebGL and projection layers have this layout.
+    Default,
     // Allocates one texture
     Mono,
     // Allocates one texture, which is split in half vertically, giving two subimages
     StereoLeftRight,
     // Allocates one texture, which is split in half horizonally, giving two subimages
     StereoTopBottom,
+    // https://immersive-web.github.io/layers/#dom-xrtexturetype-texture-array
+    // Allocates a texture array with one layer for each view, giving one subimage for each layer.
+    // At the end of each frame, the layers are copied into a texture which the device reads,
+    // with the device's viewports for projection layers, or split like StereoLeftRight.
+    TextureArray,
+}
+
+impl LayerLayout {
+    /// The size of the texture, given the size of each view
+    pub fn texture_size(&self, view_size: Size2D<i32, Viewport>) -> Size2D<i32, Viewport> {
+        match self {
+            LayerLayout::Default | LayerLayout::Mono => view_size,
+            LayerLayout::StereoLeftRight | LayerLayout::TextureArray => {
+                Size2D::new(view_size.width * 2, view_size.height)
+            }
+            LayerLayout::StereoTopBottom => Size2D::new(view_size.width, view_size.height * 2),
+        }
+    }
+
+    /// Where each view is in the texture, given the size of each view.
+    /// Mono layers have one view, which is shown to every eye.
+    pub fn view_viewports(&self, view_size: Size2D<i32, Viewport>) -> Vec<Rect<i32, Viewport>> {
+        let origin = Point2D::new(0, 0);
+        match self {
+            LayerLayout::Default | LayerLayout::Mono => vec![Rect::new(origin, view_size)],
+            LayerLayout::StereoLeftRight | LayerLayout::TextureArray => vec![
+                Rect::new(origin, view_size),
+                Rect::new(Point2D::new(view_size.width, 0), view_size),
+            ],
+            // The texture origin is at the bottom, so the left eye is the top half
+            LayerLayout::StereoTopBottom => vec![
+                Rect::new(Point2D::new(0, view_size.height), view_size),
+                Rect::new(origin, view_size),
+            ],
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
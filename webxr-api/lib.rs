@@ -4,22 +4,41 @@
 
 //! This crate defines the Rust API for WebXR. It is implemented by the `webxr` crate.
 
+mod anchor;
+mod capabilities;
 mod device;
 mod error;
 mod events;
 mod frame;
+#[cfg(feature = "async")]
+mod future;
+mod gesture;
 mod hand;
+mod hand_pose;
 mod hittest;
 mod input;
 mod layer;
+mod mesh;
 mod mock;
+mod plane;
 mod registry;
 mod session;
 mod space;
+mod stats;
 pub mod util;
 mod view;
 
+pub use anchor::AnchorId;
+pub use anchor::AnchorPose;
+pub use anchor::AnchorSource;
+pub use anchor::AnchorSpace;
+
+pub use capabilities::DeviceCapabilities;
+
 pub use device::DeviceAPI;
+pub use device::DeviceDescriptor;
+pub use device::DeviceEvent;
+pub use device::DeviceId;
 pub use device::DiscoveryAPI;
 
 pub use error::Error;
@@ -32,6 +51,26 @@ pub use frame::Frame;
 pub use frame::FrameUpdateEvent;
 pub use frame::ViewerPose;
 
+#[cfg(feature = "async")]
+pub use future::session_events;
+#[cfg(feature = "async")]
+pub use future::AsyncRegistry;
+#[cfg(feature = "async")]
+pub use future::DeviceEventStream;
+#[cfg(feature = "async")]
+pub use future::EventStream;
+#[cfg(feature = "async")]
+pub use future::FrameStream;
+#[cfg(feature = "async")]
+pub use future::Message;
+#[cfg(feature = "async")]
+pub use future::ReceiverStream;
+#[cfg(feature = "async")]
+pub use future::Recv;
+
+pub use gesture::GestureRecognizer;
+pub use gesture::GestureThresholds;
+
 pub use hand::Finger;
 pub use hand::FingerJoint;
 pub use hand::Hand;
@@ -39,6 +78,10 @@ pub use hand::HandSpace;
 pub use hand::Joint;
 pub use hand::JointFrame;
 
+pub use hand_pose::interpolate_hand;
+pub use hand_pose::HandPose;
+pub use hand_pose::HandTransition;
+
 pub use hittest::EntityType;
 pub use hittest::EntityTypes;
 pub use hittest::HitTestId;
@@ -46,8 +89,13 @@ pub use hittest::HitTestResult;
 pub use hittest::HitTestSource;
 pub use hittest::HitTestSpace;
 pub use hittest::Ray;
+pub use hittest::TransientHitTestResult;
+pub use hittest::TransientHitTestSource;
 pub use hittest::Triangle;
 
+pub use input::Gamepad;
+pub use input::GamepadButton;
+pub use input::GamepadMapping;
 pub use input::Handedness;
 pub use input::InputFrame;
 pub use input::InputId;
@@ -67,19 +115,33 @@ pub use layer::LayerLayout;
 pub use layer::LayerManager;
 pub use layer::LayerManagerAPI;
 pub use layer::LayerManagerFactory;
+pub use layer::LayerType;
 pub use layer::SubImage;
 pub use layer::SubImages;
 
+pub use mesh::Mesh;
+pub use mesh::MeshId;
+pub use mesh::MeshSpace;
+
 pub use mock::MockDeviceInit;
 pub use mock::MockDeviceMsg;
 pub use mock::MockDiscoveryAPI;
+pub use mock::MockFramePacing;
 pub use mock::MockInputInit;
 pub use mock::MockInputMsg;
+pub use mock::MockPlane;
 pub use mock::MockRegion;
+pub use mock::MockTimeline;
 pub use mock::MockViewInit;
 pub use mock::MockViewsInit;
 pub use mock::MockWorld;
 
+pub use plane::Plane;
+pub use plane::PlaneId;
+pub use plane::PlaneOrientation;
+pub use plane::PlaneSpace;
+
+pub use registry::DeviceNotifier;
 pub use registry::MainThreadRegistry;
 pub use registry::MainThreadWaker;
 pub use registry::Registry;
@@ -96,8 +158,11 @@ pub use session::SessionThread;
 
 pub use space::ApiSpace;
 pub use space::BaseSpace;
+pub use space::ReferenceSpaceType;
 pub use space::Space;
 
+pub use stats::FrameStats;
+
 pub use view::Capture;
 pub use view::CubeBack;
 pub use view::CubeBottom;
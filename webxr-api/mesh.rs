@@ -0,0 +1,32 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::Native;
+
+use euclid::Point3D;
+use euclid::RigidTransform3D;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct MeshId(pub u32);
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// The coordinate space of a mesh
+pub struct MeshSpace;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// https://immersive-web.github.io/real-world-geometry/webxrmeshing-1.html#xrmesh
+pub struct Mesh {
+    pub id: MeshId,
+    pub pose: RigidTransform3D<f32, MeshSpace, Native>,
+    pub vertices: Vec<Point3D<f32, MeshSpace>>,
+    /// The triangles of the mesh, as triples of indices into `vertices`
+    pub indices: Vec<u32>,
+    /// What the mesh is, e.g. "floor" or "table", if known
+    pub semantic_label: Option<String>,
+    /// The time at which the mesh last changed, in the same clock as `Frame::time_ns`
+    pub last_changed_time: u64,
+}
@@ -7,12 +7,19 @@ use crate::Display;
 use crate::EntityType;
 use crate::Error;
 use crate::Floor;
+use crate::Gamepad;
+use crate::GamepadButton;
+use crate::Hand;
 use crate::Handedness;
 use crate::Input;
 use crate::InputId;
 use crate::InputSource;
+use crate::Joint;
+use crate::JointFrame;
 use crate::LeftEye;
 use crate::Native;
+use crate::PlaneOrientation;
+use crate::PlaneSpace;
 use crate::Receiver;
 use crate::RightEye;
 use crate::SelectEvent;
@@ -24,7 +31,7 @@ use crate::Viewer;
 use crate::Viewport;
 use crate::Visibility;
 
-use euclid::{Rect, RigidTransform3D, Transform3D};
+use euclid::{Point3D, Rect, RigidTransform3D, Transform3D};
 
 #[cfg(feature = "ipc")]
 use serde::{Deserialize, Serialize};
@@ -49,6 +56,8 @@ pub struct MockDeviceInit {
     pub views: MockViewsInit,
     pub supported_features: Vec<String>,
     pub world: Option<MockWorld>,
+    /// The boundary of the play area, for the bounded-floor reference space
+    pub bounds_geometry: Option<Vec<Point3D<f32, Floor>>>,
 }
 
 #[derive(Clone, Debug)]
@@ -73,21 +82,54 @@ pub enum MockViewsInit {
 pub enum MockDeviceMsg {
     SetViewerOrigin(Option<RigidTransform3D<f32, Viewer, Native>>),
     SetFloorOrigin(Option<RigidTransform3D<f32, Floor, Native>>),
+    SetBoundsGeometry(Option<Vec<Point3D<f32, Floor>>>),
+    /// Recenter the device, moving the local origin horizontally to the viewer,
+    /// and turning it to face the same way as the viewer
+    /// https://immersive-web.github.io/webxr-test-api/#dom-fakexrdevice-simulateresetpose
+    SimulateResetPose,
     SetViews(MockViewsInit),
     AddInputSource(MockInputInit),
     MessageInputSource(InputId, MockInputMsg),
+    /// Change the visibility of the device itself, which its sessions are no more visible than
+    /// https://immersive-web.github.io/webxr-test-api/#dom-fakexrdevice-simulatevisibilitychange
     VisibilityChange(Visibility),
     SetWorld(MockWorld),
     ClearWorld,
+    /// Schedule messages to be applied at the beginning of given frames
+    SetTimeline(MockTimeline),
+    SetFramePacing(MockFramePacing),
+    /// Advance the device clock, in nanoseconds
+    AdvanceClock(u64),
     Disconnect(Sender<()>),
 }
 
+/// How often a mock device produces animation frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub enum MockFramePacing {
+    /// A target frame rate, in frames per second, which must be positive
+    FixedRate(f32),
+    /// Produce frames as soon as they are requested
+    AsFastAsPossible,
+    /// The device clock is frozen, and each frame waits for the
+    /// clock to be advanced by `MockDeviceMsg::AdvanceClock`
+    Manual,
+}
+
+impl Default for MockFramePacing {
+    fn default() -> MockFramePacing {
+        MockFramePacing::FixedRate(50.0)
+    }
+}
+
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
 pub struct MockInputInit {
     pub source: InputSource,
     pub pointer_origin: Option<RigidTransform3D<f32, Input, Native>>,
     pub grip_origin: Option<RigidTransform3D<f32, Input, Native>>,
+    pub hand: Option<Box<Hand<JointFrame>>>,
+    pub gamepad: Option<Gamepad>,
 }
 
 #[derive(Debug)]
@@ -98,6 +140,19 @@ pub enum MockInputMsg {
     SetProfiles(Vec<String>),
     SetPointerOrigin(Option<RigidTransform3D<f32, Input, Native>>),
     SetGripOrigin(Option<RigidTransform3D<f32, Input, Native>>),
+    /// Set the hand support advertised by the input source
+    SetHandSupport(Option<Hand<()>>),
+    /// Set the pose of the whole hand, or stop tracking it
+    SetHand(Option<Box<Hand<JointFrame>>>),
+    /// Set the pose of a single joint, or stop tracking it.
+    /// Gestures are recognized once per frame, after all the joints are updated.
+    SetHandJoint(Joint, Option<JointFrame>),
+    /// Set the whole gamepad, or remove it
+    SetGamepad(Option<Gamepad>),
+    /// Set a gamepad button, adding an xr-standard gamepad if there is none
+    SetGamepadButton(usize, GamepadButton),
+    /// Set a gamepad axis, adding an xr-standard gamepad if there is none
+    SetGamepadAxis(usize, f32),
     /// Note: SelectEvent::Select here refers to a complete Select event,
     /// not just the end event, i.e. it refers to
     /// https://immersive-web.github.io/webxr-test-api/#dom-fakexrinputcontroller-simulateselect
@@ -109,12 +164,61 @@ pub enum MockInputMsg {
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
 pub struct MockRegion {
+    /// Identifies the region as the world changes, so that
+    /// the mesh and plane detected from it keep their identity
+    pub id: u32,
     pub faces: Vec<Triangle>,
     pub ty: EntityType,
+    /// The semantic label of the mesh made from this region
+    pub semantic_label: Option<String>,
 }
 
 #[derive(Clone, Debug)]
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
 pub struct MockWorld {
     pub regions: Vec<MockRegion>,
+    /// Planes to be detected, in addition to those made of
+    /// the coplanar faces of plane regions
+    pub planes: Vec<MockPlane>,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct MockPlane {
+    /// Identifies the plane as the world changes, so that
+    /// the detected plane keeps its identity
+    pub id: u32,
+    pub pose: RigidTransform3D<f32, PlaneSpace, Native>,
+    pub polygon: Vec<Point3D<f32, PlaneSpace>>,
+    pub orientation: Option<PlaneOrientation>,
+}
+
+/// A script of mock device messages, keyed by frame number.
+///
+/// Frames are counted from zero, starting with the first animation frame
+/// begun by an immersive session on the device; the frames of inline sessions
+/// are not counted. Each message is applied at the beginning of its frame,
+/// before the frame data is computed, which makes it possible to replay
+/// a sequence of device changes without racing the frame loop.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct MockTimeline {
+    pub entries: Vec<(u64, MockDeviceMsg)>,
+}
+
+impl MockTimeline {
+    pub fn new() -> MockTimeline {
+        MockTimeline::default()
+    }
+
+    /// Add a message to be applied at the beginning of the given frame
+    pub fn at(mut self, frame: u64, msg: MockDeviceMsg) -> MockTimeline {
+        self.entries.push((frame, msg));
+        self
+    }
+
+    /// Add a message for an input source to be applied at the beginning of the given frame
+    pub fn at_input(self, frame: u64, id: InputId, msg: MockInputMsg) -> MockTimeline {
+        self.at(frame, MockDeviceMsg::MessageInputSource(id, msg))
+    }
 }
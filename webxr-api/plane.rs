@@ -0,0 +1,39 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+use crate::Native;
+
+use euclid::Point3D;
+use euclid::RigidTransform3D;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+pub struct PlaneId(pub u32);
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// The coordinate space of a plane, in which the plane is the XZ plane
+pub struct PlaneSpace;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// https://immersive-web.github.io/real-world-geometry/plane-detection.html#enumdef-xrplaneorientation
+pub enum PlaneOrientation {
+    Horizontal,
+    Vertical,
+}
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// https://immersive-web.github.io/real-world-geometry/plane-detection.html#xrplane
+pub struct Plane {
+    pub id: PlaneId,
+    /// The pose of the plane, whose Y axis is the plane normal
+    pub pose: RigidTransform3D<f32, PlaneSpace, Native>,
+    /// The vertices of the outline of the plane, which all have a Y coordinate of zero
+    pub polygon: Vec<Point3D<f32, PlaneSpace>>,
+    pub orientation: Option<PlaneOrientation>,
+    /// The time at which the plane last changed, in the same clock as `Frame::time_ns`
+    pub last_changed_time: u64,
+}
@@ -2,6 +2,10 @@
  * License, v. 2.0. If a copy of the MPL was not distributed with this
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
+use crate::session::SessionHandle;
+use crate::DeviceDescriptor;
+use crate::DeviceEvent;
+use crate::DeviceId;
 use crate::DiscoveryAPI;
 use crate::Error;
 use crate::Frame;
@@ -18,6 +22,7 @@ use crate::SessionBuilder;
 use crate::SessionId;
 use crate::SessionInit;
 use crate::SessionMode;
+use crate::Visibility;
 
 use log::warn;
 
@@ -32,14 +37,68 @@ pub struct Registry {
 }
 
 pub struct MainThreadRegistry<GL> {
-    discoveries: Vec<Box<dyn DiscoveryAPI<GL>>>,
+    /// The registered devices, highest priority first
+    discoveries: Vec<RegisteredDiscovery<GL>>,
     sessions: Vec<Box<dyn MainThreadSession>>,
     mocks: Vec<Box<dyn MockDiscoveryAPI<GL>>>,
     sender: Sender<RegistryMsg>,
     receiver: Receiver<RegistryMsg>,
     waker: MainThreadWakerImpl,
     grand_manager: LayerGrandManager<GL>,
+    next_device_id: u32,
     next_session_id: u32,
+    live_sessions: Vec<LiveSession>,
+    /// The session the user is interacting with, if no immersive session is running
+    focused_session: Option<SessionId>,
+    device_event_dests: Vec<Sender<DeviceEvent>>,
+}
+
+struct RegisteredDiscovery<GL> {
+    id: DeviceId,
+    priority: i32,
+    connected: bool,
+    /// The visibility of the device itself, which its sessions can't exceed
+    visibility: Visibility,
+    discovery: Box<dyn DiscoveryAPI<GL>>,
+}
+
+/// A handle which a discovery can use to tell the registry
+/// when its device is plugged in, unplugged or changes
+#[derive(Clone)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct DeviceNotifier {
+    registry: Registry,
+    id: DeviceId,
+}
+
+impl DeviceNotifier {
+    pub fn connected(&mut self) {
+        self.registry.device_connected(self.id, true);
+    }
+
+    pub fn disconnected(&mut self) {
+        self.registry.device_connected(self.id, false);
+    }
+
+    /// The device's descriptor has changed, for example it supports different features
+    pub fn changed(&mut self) {
+        self.registry.device_changed(self.id);
+    }
+
+    /// The device's own visibility has changed, for example it is being covered by
+    /// a system menu, so none of its sessions can be more visible than this
+    pub fn set_visibility(&mut self, visibility: Visibility) {
+        self.registry.device_visibility(self.id, visibility);
+    }
+}
+
+/// A session which has been created and not yet ended
+struct LiveSession {
+    id: SessionId,
+    device: DeviceId,
+    mode: SessionMode,
+    handle: SessionHandle,
+    visibility: Visibility,
 }
 
 pub trait MainThreadWaker: 'static + Send {
@@ -103,12 +162,71 @@ impl Registry {
         let _ = self.sender.send(RegistryMsg::RequestSession(
             mode,
             init,
+            None,
+            dest,
+            animation_frame_handler,
+        ));
+        self.waker.wake();
+    }
+
+    /// Request a session from a particular device, rather than the
+    /// highest priority device which supports the mode
+    pub fn request_session_on_device(
+        &mut self,
+        device: DeviceId,
+        mode: SessionMode,
+        init: SessionInit,
+        dest: Sender<Result<Session, Error>>,
+        animation_frame_handler: Sender<Frame>,
+    ) {
+        let _ = self.sender.send(RegistryMsg::RequestSession(
+            mode,
+            init,
+            Some(device),
             dest,
             animation_frame_handler,
         ));
         self.waker.wake();
     }
 
+    /// Give focus to a session, for example when the user switches to its tab.
+    /// This has no effect while an immersive session is running, since that has focus.
+    pub fn set_focused_session(&mut self, id: Option<SessionId>) {
+        let _ = self.sender.send(RegistryMsg::SetFocusedSession(id));
+        self.waker.wake();
+    }
+
+    /// Listen for devices being connected, disconnected or changed.
+    /// https://immersive-web.github.io/webxr/#eventdef-xrsystem-devicechange
+    pub fn add_device_event_dest(&mut self, dest: Sender<DeviceEvent>) {
+        let _ = self.sender.send(RegistryMsg::AddDeviceEventDest(dest));
+        self.waker.wake();
+    }
+
+    fn device_connected(&mut self, id: DeviceId, connected: bool) {
+        let _ = self
+            .sender
+            .send(RegistryMsg::DeviceConnected(id, connected));
+        self.waker.wake();
+    }
+
+    fn device_changed(&mut self, id: DeviceId) {
+        let _ = self.sender.send(RegistryMsg::DeviceChanged(id));
+        self.waker.wake();
+    }
+
+    fn device_visibility(&mut self, id: DeviceId, visibility: Visibility) {
+        let _ = self
+            .sender
+            .send(RegistryMsg::DeviceVisibility(id, visibility));
+        self.waker.wake();
+    }
+
+    pub(crate) fn session_ended(&mut self, id: SessionId) {
+        let _ = self.sender.send(RegistryMsg::SessionEnded(id));
+        self.waker.wake();
+    }
+
     pub fn simulate_device_connection(
         &mut self,
         init: MockDeviceInit,
@@ -139,7 +257,11 @@ impl<GL: 'static + GLTypes> MainThreadRegistry<GL> {
             receiver,
             waker,
             grand_manager,
+            next_device_id: 0,
             next_session_id: 0,
+            live_sessions: Vec::new(),
+            focused_session: None,
+            device_event_dests: Vec::new(),
         })
     }
 
@@ -150,11 +272,11 @@ impl<GL: 'static + GLTypes> MainThreadRegistry<GL> {
         }
     }
 
-    pub fn register<D>(&mut self, discovery: D)
+    pub fn register<D>(&mut self, discovery: D) -> DeviceId
     where
         D: DiscoveryAPI<GL>,
     {
-        self.discoveries.push(Box::new(discovery));
+        self.add_discovery(Box::new(discovery), false)
     }
 
     pub fn register_mock<D>(&mut self, discovery: D)
@@ -164,6 +286,63 @@ impl<GL: 'static + GLTypes> MainThreadRegistry<GL> {
         self.mocks.push(Box::new(discovery));
     }
 
+    /// The connected devices, in the order they are tried when a session is requested
+    pub fn devices(&self) -> Vec<(DeviceId, DeviceDescriptor)> {
+        self.discoveries
+            .iter()
+            .filter(|registered| registered.connected)
+            .map(|registered| (registered.id, registered.discovery.descriptor()))
+            .collect()
+    }
+
+    /// Devices with a higher priority are tried first. Devices start with priority 0,
+    /// and devices with the same priority are tried with the most recent mock first,
+    /// then in the order they were registered.
+    pub fn set_device_priority(&mut self, id: DeviceId, priority: i32) {
+        if let Some(registered) = self.discoveries.iter_mut().find(|d| d.id == id) {
+            registered.priority = priority;
+        }
+        self.sort_discoveries();
+    }
+
+    /// Mock devices go in front of the other devices with the same priority
+    fn add_discovery(&mut self, mut discovery: Box<dyn DiscoveryAPI<GL>>, mock: bool) -> DeviceId {
+        let id = DeviceId(self.next_device_id);
+        self.next_device_id += 1;
+        discovery.set_device_notifier(DeviceNotifier {
+            registry: self.registry(),
+            id,
+        });
+        let descriptor = discovery.descriptor();
+        let registered = RegisteredDiscovery {
+            id,
+            priority: 0,
+            connected: true,
+            visibility: Visibility::Visible,
+            discovery,
+        };
+        if mock {
+            self.discoveries.insert(0, registered);
+        } else {
+            self.discoveries.push(registered);
+        }
+        self.sort_discoveries();
+        self.device_event(DeviceEvent::Added(id, descriptor));
+        id
+    }
+
+    fn device_event(&mut self, event: DeviceEvent) {
+        // Forget about anyone who has stopped listening
+        self.device_event_dests
+            .retain(|dest| dest.send(event.clone()).is_ok());
+    }
+
+    fn sort_discoveries(&mut self) {
+        // A stable sort, so devices with the same priority keep their order
+        self.discoveries
+            .sort_by_key(|registered| -registered.priority);
+    }
+
     pub fn run_on_main_thread<S>(&mut self, session: S)
     where
         S: MainThreadSession,
@@ -190,18 +369,92 @@ impl<GL: 'static + GLTypes> MainThreadRegistry<GL> {
             RegistryMsg::SupportsSession(mode, dest) => {
                 let _ = dest.send(self.supports_session(mode));
             }
-            RegistryMsg::RequestSession(mode, init, dest, raf_sender) => {
-                let _ = dest.send(self.request_session(mode, init, raf_sender));
+            RegistryMsg::RequestSession(mode, init, device, dest, raf_sender) => {
+                let _ = dest.send(self.request_session(mode, init, device, raf_sender));
             }
             RegistryMsg::SimulateDeviceConnection(init, dest) => {
                 let _ = dest.send(self.simulate_device_connection(init));
             }
+            RegistryMsg::SetFocusedSession(id) => {
+                self.focused_session = id;
+                self.update_focus();
+            }
+            RegistryMsg::AddDeviceEventDest(dest) => {
+                self.device_event_dests.push(dest);
+            }
+            RegistryMsg::DeviceConnected(id, connected) => {
+                let registered = match self.discoveries.iter_mut().find(|d| d.id == id) {
+                    Some(registered) if registered.connected != connected => registered,
+                    _ => return,
+                };
+                registered.connected = connected;
+                let event = if connected {
+                    DeviceEvent::Added(id, registered.discovery.descriptor())
+                } else {
+                    DeviceEvent::Removed(id)
+                };
+                self.device_event(event);
+            }
+            RegistryMsg::DeviceChanged(id) => {
+                let descriptor = match self.discoveries.iter().find(|d| d.id == id) {
+                    Some(registered) if registered.connected => registered.discovery.descriptor(),
+                    _ => return,
+                };
+                self.device_event(DeviceEvent::Changed(id, descriptor));
+            }
+            RegistryMsg::DeviceVisibility(id, visibility) => {
+                if let Some(registered) = self.discoveries.iter_mut().find(|d| d.id == id) {
+                    registered.visibility = visibility;
+                    self.update_focus();
+                }
+            }
+            RegistryMsg::SessionEnded(id) => {
+                self.live_sessions.retain(|session| session.id != id);
+                if self.focused_session == Some(id) {
+                    self.focused_session = self.live_sessions.last().map(|session| session.id);
+                }
+                self.update_focus();
+            }
+        }
+    }
+
+    /// Only one session has focus: the immersive session if there is one,
+    /// otherwise the focused inline session. While an immersive session is
+    /// running, inline sessions are hidden, otherwise they are just blurred.
+    /// Sessions are also no more visible than their device.
+    /// https://immersive-web.github.io/webxr/#xrsession-visibility-state
+    fn update_focus(&mut self) {
+        let immersive = self
+            .live_sessions
+            .iter()
+            .find(|session| session.mode != SessionMode::Inline)
+            .map(|session| session.id);
+        let focused = immersive.or(self.focused_session);
+        for session in &mut self.live_sessions {
+            let visibility = if Some(session.id) == focused {
+                Visibility::Visible
+            } else if immersive.is_some() {
+                Visibility::Hidden
+            } else {
+                Visibility::VisibleBlurred
+            };
+            let device_visibility = self
+                .discoveries
+                .iter()
+                .find(|registered| registered.id == session.device)
+                .map(|registered| registered.visibility)
+                .unwrap_or(Visibility::Visible);
+            let visibility = least_visible(visibility, device_visibility);
+            if session.visibility != visibility {
+                session.visibility = visibility;
+                session.handle.set_visibility(visibility);
+            }
         }
     }
 
     fn supports_session(&mut self, mode: SessionMode) -> Result<(), Error> {
-        for discovery in &self.discoveries {
-            if discovery.supports_session(mode) {
+        for registered in &self.discoveries {
+            if registered.connected && registered.discovery.supports_session(mode) {
                 return Ok(());
             }
         }
@@ -212,9 +465,28 @@ impl<GL: 'static + GLTypes> MainThreadRegistry<GL> {
         &mut self,
         mode: SessionMode,
         init: SessionInit,
+        device: Option<DeviceId>,
         raf_sender: Sender<Frame>,
     ) -> Result<Session, Error> {
-        for discovery in &mut self.discoveries {
+        // https://immersive-web.github.io/webxr/#dom-xrsystem-requestsession
+        if mode != SessionMode::Inline
+            && self
+                .live_sessions
+                .iter()
+                .any(|session| session.mode != SessionMode::Inline)
+        {
+            return Err(Error::InvalidState(
+                "an immersive session is already running".into(),
+            ));
+        }
+        let registry = self.registry();
+        let discoveries = self
+            .discoveries
+            .iter_mut()
+            .filter(|registered| registered.connected)
+            .filter(|registered| device.is_none() || device == Some(registered.id));
+        for registered in discoveries {
+            let discovery = &mut registered.discovery;
             if discovery.supports_session(mode) {
                 let raf_sender = raf_sender.clone();
                 let id = SessionId(self.next_session_id);
@@ -224,9 +496,22 @@ impl<GL: 'static + GLTypes> MainThreadRegistry<GL> {
                     raf_sender,
                     self.grand_manager.clone(),
                     id,
+                    registry.clone(),
                 );
                 match discovery.request_session(mode, &init, xr) {
-                    Ok(session) => return Ok(session),
+                    Ok(session) => {
+                        // The newest session takes focus
+                        self.live_sessions.push(LiveSession {
+                            id,
+                            device: registered.id,
+                            mode,
+                            handle: session.handle(),
+                            visibility: Visibility::Visible,
+                        });
+                        self.focused_session = Some(id);
+                        self.update_focus();
+                        return Ok(session);
+                    }
                     Err(err) => warn!("XR device error {:?}", err),
                 }
             }
@@ -239,10 +524,11 @@ impl<GL: 'static + GLTypes> MainThreadRegistry<GL> {
         &mut self,
         init: MockDeviceInit,
     ) -> Result<Sender<MockDeviceMsg>, Error> {
-        for mock in &mut self.mocks {
+        for i in 0..self.mocks.len() {
             let (sender, receiver) = crate::channel().or(Err(Error::CommunicationError))?;
-            if let Ok(discovery) = mock.simulate_device_connection(init.clone(), receiver) {
-                self.discoveries.insert(0, discovery);
+            if let Ok(discovery) = self.mocks[i].simulate_device_connection(init.clone(), receiver)
+            {
+                self.add_discovery(discovery, true);
                 return Ok(sender);
             }
         }
@@ -255,9 +541,27 @@ enum RegistryMsg {
     RequestSession(
         SessionMode,
         SessionInit,
+        Option<DeviceId>,
         Sender<Result<Session, Error>>,
         Sender<Frame>,
     ),
     SupportsSession(SessionMode, Sender<Result<(), Error>>),
     SimulateDeviceConnection(MockDeviceInit, Sender<Result<Sender<MockDeviceMsg>, Error>>),
+    SetFocusedSession(Option<SessionId>),
+    SessionEnded(SessionId),
+    AddDeviceEventDest(Sender<DeviceEvent>),
+    DeviceConnected(DeviceId, bool),
+    DeviceChanged(DeviceId),
+    DeviceVisibility(DeviceId, Visibility),
+}
+
+/// The less visible of two visibilities
+fn least_visible(a: Visibility, b: Visibility) -> Visibility {
+    match (a, b) {
+        (Visibility::Hidden, _) | (_, Visibility::Hidden) => Visibility::Hidden,
+        (Visibility::VisibleBlurred, _) | (_, Visibility::VisibleBlurred) => {
+            Visibility::VisibleBlurred
+        }
+        (Visibility::Visible, Visibility::Visible) => Visibility::Visible,
+    }
 }
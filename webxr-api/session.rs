@@ -3,12 +3,17 @@
  * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
 
 use crate::channel;
+use crate::stats::FrameStatsHistory;
+use crate::AnchorId;
+use crate::AnchorSource;
 use crate::ContextId;
 use crate::DeviceAPI;
+use crate::DeviceCapabilities;
 use crate::Error;
 use crate::Event;
 use crate::Floor;
 use crate::Frame;
+use crate::FrameStats;
 use crate::FrameUpdateEvent;
 use crate::HitTestId;
 use crate::HitTestSource;
@@ -16,12 +21,18 @@ use crate::InputSource;
 use crate::LayerGrandManager;
 use crate::LayerId;
 use crate::LayerInit;
+use crate::Mesh;
 use crate::Native;
+use crate::Plane;
 use crate::Receiver;
+use crate::Registry;
 use crate::Sender;
+use crate::TransientHitTestSource;
 use crate::Viewport;
 use crate::Viewports;
+use crate::Visibility;
 
+use euclid::Point3D;
 use euclid::Rect;
 use euclid::RigidTransform3D;
 use euclid::Size2D;
@@ -65,7 +76,7 @@ impl SessionInit {
                 continue;
             }
 
-            if !supported.contains(f) {
+            if !supported.contains(f) || !allowed_in_mode(f, mode) {
                 return Err(Error::UnsupportedFeature(f.into()));
             }
         }
@@ -73,7 +84,7 @@ impl SessionInit {
         for f in &self.optional_features {
             if f == "viewer"
                 || (f == "local" && mode != SessionMode::Inline)
-                || supported.contains(f)
+                || (supported.contains(f) && allowed_in_mode(f, mode))
             {
                 granted.push(f.clone());
             }
@@ -91,9 +102,10 @@ impl SessionInit {
     }
 }
 
-#[cfg(feature = "profile")]
-fn to_ms(ns: u64) -> f64 {
-    ns as f64 / 1_000_000.
+/// The bounded-floor and unbounded reference spaces are only available to immersive sessions
+/// https://immersive-web.github.io/webxr/#feature-requirements
+fn allowed_in_mode(feature: &str, mode: SessionMode) -> bool {
+    mode != SessionMode::Inline || (feature != "bounded-floor" && feature != "unbounded")
 }
 
 /// https://immersive-web.github.io/webxr-ar-module/#xrenvironmentblendmode-enum
@@ -116,8 +128,13 @@ enum SessionMsg {
     UpdateClipPlanes(/* near */ f32, /* far */ f32),
     StartRenderLoop,
     RenderAnimationFrame(/* request time */ u64),
+    TakeFrameStats(Sender<Vec<FrameStats>>),
     RequestHitTest(HitTestSource),
+    RequestTransientHitTest(TransientHitTestSource),
     CancelHitTest(HitTestId),
+    CreateAnchor(AnchorSource, Sender<Result<AnchorId, Error>>),
+    DeleteAnchor(AnchorId),
+    SetVisibility(Visibility),
     Quit,
 }
 
@@ -133,6 +150,18 @@ impl Quitter {
     }
 }
 
+/// The registry's handle on a session, used to tell it when it gains or loses focus
+#[derive(Clone)]
+pub(crate) struct SessionHandle {
+    sender: Sender<SessionMsg>,
+}
+
+impl SessionHandle {
+    pub(crate) fn set_visibility(&self, visibility: Visibility) {
+        let _ = self.sender.send(SessionMsg::SetVisibility(visibility));
+    }
+}
+
 /// An object that represents an XR session.
 /// This is owned by the content thread.
 /// https://www.w3.org/TR/webxr/#xrsession-interface
@@ -140,10 +169,14 @@ impl Quitter {
 pub struct Session {
     floor_transform: Option<RigidTransform3D<f32, Native, Floor>>,
     viewports: Viewports,
+    bounds_geometry: Option<Vec<Point3D<f32, Floor>>>,
     sender: Sender<SessionMsg>,
     environment_blend_mode: EnvironmentBlendMode,
     initial_inputs: Vec<InputSource>,
     granted_features: Vec<String>,
+    capabilities: DeviceCapabilities,
+    detected_planes: Vec<Plane>,
+    detected_meshes: Vec<Mesh>,
     id: SessionId,
 }
 
@@ -156,10 +189,21 @@ impl Session {
         self.id
     }
 
+    pub(crate) fn handle(&self) -> SessionHandle {
+        SessionHandle {
+            sender: self.sender.clone(),
+        }
+    }
+
     pub fn floor_transform(&self) -> Option<RigidTransform3D<f32, Native, Floor>> {
         self.floor_transform.clone()
     }
 
+    /// https://immersive-web.github.io/webxr/#dom-xrboundedreferencespace-boundsgeometry
+    pub fn bounds_geometry(&self) -> Option<&[Point3D<f32, Floor>]> {
+        self.bounds_geometry.as_ref().map(|bounds| &bounds[..])
+    }
+
     pub fn initial_inputs(&self) -> &[InputSource] {
         &self.initial_inputs
     }
@@ -186,11 +230,12 @@ impl Session {
     }
 
     pub fn create_layer(&self, context_id: ContextId, init: LayerInit) -> Result<LayerId, Error> {
+        self.capabilities.check_layer(&init)?;
         let (sender, receiver) = channel().map_err(|_| Error::CommunicationError)?;
         let _ = self
             .sender
             .send(SessionMsg::CreateLayer(context_id, init, sender));
-        receiver.recv().map_err(|_| Error::CommunicationError)?
+        receiver.recv().map_err(|_| Error::SessionEnded)?
     }
 
     /// Destroy a layer
@@ -217,15 +262,19 @@ impl Session {
     }
 
     pub fn render_animation_frame(&mut self) {
-        #[allow(unused)]
-        let mut time = 0;
-        #[cfg(feature = "profile")]
-        {
-            time = time::precise_time_ns();
-        }
+        let time = time::precise_time_ns();
         let _ = self.sender.send(SessionMsg::RenderAnimationFrame(time));
     }
 
+    /// Take the statistics for the frames rendered since this was last called,
+    /// up to a limit, oldest first. With the profile feature, they are also logged
+    /// as they are collected, with target `webxr::frame_stats`.
+    pub fn take_frame_stats(&self) -> Result<Vec<FrameStats>, Error> {
+        let (sender, receiver) = channel().map_err(|_| Error::CommunicationError)?;
+        let _ = self.sender.send(SessionMsg::TakeFrameStats(sender));
+        receiver.recv().map_err(|_| Error::SessionEnded)
+    }
+
     pub fn end_session(&mut self) {
         let _ = self.sender.send(SessionMsg::Quit);
     }
@@ -234,7 +283,23 @@ impl Session {
         match event {
             FrameUpdateEvent::UpdateFloorTransform(floor) => self.floor_transform = floor,
             FrameUpdateEvent::UpdateViewports(vp) => self.viewports = vp,
+            FrameUpdateEvent::UpdateBoundsGeometry(bounds) => self.bounds_geometry = bounds,
             FrameUpdateEvent::HitTestSourceAdded(_) => (),
+            FrameUpdateEvent::AnchorLost(_) => (),
+            FrameUpdateEvent::PlaneAdded(plane) => self.detected_planes.push(plane),
+            FrameUpdateEvent::PlaneChanged(plane) => {
+                if let Some(old) = self.detected_planes.iter_mut().find(|p| p.id == plane.id) {
+                    *old = plane;
+                }
+            }
+            FrameUpdateEvent::PlaneRemoved(id) => self.detected_planes.retain(|p| p.id != id),
+            FrameUpdateEvent::MeshAdded(mesh) => self.detected_meshes.push(mesh),
+            FrameUpdateEvent::MeshChanged(mesh) => {
+                if let Some(old) = self.detected_meshes.iter_mut().find(|m| m.id == mesh.id) {
+                    *old = mesh;
+                }
+            }
+            FrameUpdateEvent::MeshRemoved(id) => self.detected_meshes.retain(|m| m.id != id),
         }
     }
 
@@ -242,13 +307,55 @@ impl Session {
         &self.granted_features
     }
 
-    pub fn request_hit_test(&self, source: HitTestSource) {
+    /// What the device can do for this session, as it was when the session started
+    pub fn capabilities(&self) -> &DeviceCapabilities {
+        &self.capabilities
+    }
+
+    /// https://immersive-web.github.io/real-world-geometry/plane-detection.html#dom-xrframe-detectedplanes
+    pub fn detected_planes(&self) -> &[Plane] {
+        &self.detected_planes
+    }
+
+    /// https://immersive-web.github.io/real-world-geometry/webxrmeshing-1.html#dom-xrframe-worldinformation
+    pub fn detected_meshes(&self) -> &[Mesh] {
+        &self.detected_meshes
+    }
+
+    pub fn request_hit_test(&self, source: HitTestSource) -> Result<(), Error> {
+        self.capabilities.check_hit_test(&source)?;
         let _ = self.sender.send(SessionMsg::RequestHitTest(source));
+        Ok(())
+    }
+
+    /// https://immersive-web.github.io/hit-test/#dom-xrsession-requesthittestsourcefortransientinput
+    pub fn request_hit_test_for_transient_input(
+        &self,
+        source: TransientHitTestSource,
+    ) -> Result<(), Error> {
+        self.capabilities.check_transient_hit_test(&source)?;
+        let _ = self
+            .sender
+            .send(SessionMsg::RequestTransientHitTest(source));
+        Ok(())
     }
 
     pub fn cancel_hit_test(&self, id: HitTestId) {
         let _ = self.sender.send(SessionMsg::CancelHitTest(id));
     }
+
+    /// https://immersive-web.github.io/anchors/#anchor-creation
+    pub fn create_anchor(&self, source: AnchorSource) -> Result<AnchorId, Error> {
+        self.capabilities.check_anchor(&source)?;
+        let (sender, receiver) = channel().map_err(|_| Error::CommunicationError)?;
+        let _ = self.sender.send(SessionMsg::CreateAnchor(source, sender));
+        receiver.recv().map_err(|_| Error::SessionEnded)?
+    }
+
+    /// https://immersive-web.github.io/anchors/#dom-xranchor-delete
+    pub fn delete_anchor(&self, id: AnchorId) {
+        let _ = self.sender.send(SessionMsg::DeleteAnchor(id));
+    }
 }
 
 #[derive(PartialEq)]
@@ -270,6 +377,11 @@ pub struct SessionThread<Device> {
     device: Device,
     id: SessionId,
     render_state: RenderState,
+    frame_stats: FrameStatsHistory,
+    /// Whether the session has focus, as decided by the registry
+    visibility: Visibility,
+    event_dest: Option<Sender<Event>>,
+    registry: Registry,
 }
 
 impl<Device> SessionThread<Device>
@@ -280,6 +392,7 @@ where
         mut device: Device,
         frame_sender: Sender<Frame>,
         id: SessionId,
+        registry: Registry,
     ) -> Result<Self, Error> {
         let (sender, receiver) = crate::channel().or(Err(Error::CommunicationError))?;
         device.set_quitter(Quitter {
@@ -300,23 +413,33 @@ where
             running,
             id,
             render_state: RenderState::NotInRenderLoop,
+            frame_stats: Default::default(),
+            visibility: Visibility::Visible,
+            event_dest: None,
+            registry,
         })
     }
 
     pub fn new_session(&mut self) -> Session {
         let floor_transform = self.device.floor_transform();
         let viewports = self.device.viewports();
+        let bounds_geometry = self.device.bounds_geometry();
         let sender = self.sender.clone();
         let initial_inputs = self.device.initial_inputs();
         let environment_blend_mode = self.device.environment_blend_mode();
         let granted_features = self.device.granted_features().into();
+        let capabilities = self.device.capabilities();
         Session {
             floor_transform,
             viewports,
+            bounds_geometry,
             sender,
             initial_inputs,
             environment_blend_mode,
             granted_features,
+            capabilities,
+            detected_planes: vec![],
+            detected_meshes: vec![],
             id: self.id,
         }
     }
@@ -338,16 +461,56 @@ where
         log::debug!("processing {:?}", msg);
         match msg {
             SessionMsg::SetEventDest(dest) => {
+                // The session may have lost focus before it had anywhere to send events
+                if self.visibility != Visibility::Visible {
+                    let _ = dest.send(Event::VisibilityChange(self.visibility));
+                }
+                self.event_dest = Some(dest.clone());
                 self.device.set_event_dest(dest);
             }
+            SessionMsg::SetVisibility(visibility) => {
+                if self.visibility != visibility {
+                    self.visibility = visibility;
+                    if let Some(ref dest) = self.event_dest {
+                        let _ = dest.send(Event::VisibilityChange(visibility));
+                    }
+                    self.device.set_visibility(visibility);
+                }
+            }
+            // The session checks hit test requests against the device's capabilities,
+            // so these only fail if the content process misbehaves
             SessionMsg::RequestHitTest(source) => {
-                self.device.request_hit_test(source);
+                match self.device.capabilities().check_hit_test(&source) {
+                    Ok(()) => self.device.request_hit_test(source),
+                    Err(err) => warn!("Ignoring unsupported hit test request: {:?}", err),
+                }
+            }
+            SessionMsg::RequestTransientHitTest(source) => {
+                match self.device.capabilities().check_transient_hit_test(&source) {
+                    Ok(()) => self.device.request_hit_test_for_transient_input(source),
+                    Err(err) => warn!("Ignoring unsupported hit test request: {:?}", err),
+                }
             }
             SessionMsg::CancelHitTest(id) => {
                 self.device.cancel_hit_test(id);
             }
+            SessionMsg::CreateAnchor(source, sender) => {
+                let result = self
+                    .device
+                    .capabilities()
+                    .check_anchor(&source)
+                    .and_then(|()| self.device.create_anchor(source));
+                let _ = sender.send(result);
+            }
+            SessionMsg::DeleteAnchor(id) => {
+                self.device.delete_anchor(id);
+            }
             SessionMsg::CreateLayer(context_id, layer_init, sender) => {
-                let result = self.device.create_layer(context_id, layer_init);
+                let result = self
+                    .device
+                    .capabilities()
+                    .check_layer(&layer_init)
+                    .and_then(|()| self.device.create_layer(context_id, layer_init));
                 let _ = sender.send(result);
             }
             SessionMsg::DestroyLayer(context_id, layer_id) => {
@@ -361,29 +524,23 @@ where
                 if let Some(layers) = self.pending_layers.take() {
                     self.layers = layers;
                 }
-                let frame = match self.device.begin_animation_frame(&self.layers[..]) {
+                let frame = match self.begin_animation_frame() {
                     Some(frame) => frame,
                     None => {
                         warn!("Device stopped providing frames, exiting");
                         return false;
                     }
                 };
+                self.frame_stats.missed_frames(frame.time_ns);
                 self.render_state = RenderState::InRenderLoop;
                 let _ = self.frame_sender.send(frame);
             }
             SessionMsg::UpdateClipPlanes(near, far) => self.device.update_clip_planes(near, far),
-            SessionMsg::RenderAnimationFrame(_sent_time) => {
+            SessionMsg::RenderAnimationFrame(sent_time) => {
+                let frame_count = self.frame_count;
                 self.frame_count += 1;
-                #[cfg(feature = "profile")]
-                let render_start = time::precise_time_ns();
-                #[cfg(feature = "profile")]
-                println!(
-                    "WEBXR PROFILING [raf transmitted]:\t{}ms",
-                    to_ms(render_start - _sent_time)
-                );
-
-                #[cfg(feature = "profile")]
                 let end_frame = time::precise_time_ns();
+                let layer_count = self.layers.len();
                 self.device.end_animation_frame(&self.layers[..]);
 
                 if self.render_state == RenderState::PendingQuit {
@@ -391,40 +548,36 @@ where
                     return false;
                 }
 
-                #[cfg(feature = "profile")]
-                let ended_frame = time::precise_time_ns();
-                #[cfg(feature = "profile")]
-                println!(
-                    "WEBXR PROFILING [raf end frame]:\t{}ms",
-                    to_ms(ended_frame - end_frame)
-                );
-
-                #[cfg(feature = "profile")]
                 let begin_frame = time::precise_time_ns();
                 if let Some(layers) = self.pending_layers.take() {
                     self.layers = layers;
                 }
-                #[allow(unused_mut)]
-                let mut frame = match self.device.begin_animation_frame(&self.layers[..]) {
+                let mut frame = match self.begin_animation_frame() {
                     Some(frame) => frame,
                     None => {
                         warn!("Device stopped providing frames, exiting");
                         return false;
                     }
                 };
-                #[cfg(feature = "profile")]
                 let begun_frame = time::precise_time_ns();
-                #[cfg(feature = "profile")]
-                {
-                    println!(
-                        "WEBXR PROFILING [raf begin frame]:\t{}ms",
-                        to_ms(begun_frame - begin_frame)
-                    );
-                    frame.sent_time = begun_frame;
-                }
+                frame.sent_time = begun_frame;
+
+                let missed_frames = self.frame_stats.missed_frames(frame.time_ns);
+                self.frame_stats.push(FrameStats {
+                    frame_count,
+                    raf_transit_ns: end_frame.saturating_sub(sent_time),
+                    end_frame_ns: begin_frame - end_frame,
+                    begin_frame_ns: begun_frame - begin_frame,
+                    predicted_display_time_ns: frame.time_ns,
+                    missed_frames,
+                    layer_count,
+                });
 
                 let _ = self.frame_sender.send(frame);
             }
+            SessionMsg::TakeFrameStats(sender) => {
+                let _ = sender.send(self.frame_stats.take());
+            }
             SessionMsg::Quit => {
                 if self.render_state == RenderState::NotInRenderLoop {
                     self.quit();
@@ -437,12 +590,28 @@ where
         true
     }
 
+    /// Begin a frame, hiding the inputs from sessions which don't have focus
+    fn begin_animation_frame(&mut self) -> Option<Frame> {
+        let mut frame = self.device.begin_animation_frame(&self.layers[..])?;
+        if self.visibility != Visibility::Visible {
+            frame.inputs.clear();
+            frame.transient_hit_test_results.clear();
+        }
+        Some(frame)
+    }
+
     fn quit(&mut self) {
         self.render_state = RenderState::NotInRenderLoop;
         self.device.quit();
     }
 }
 
+impl<Device> Drop for SessionThread<Device> {
+    fn drop(&mut self) {
+        self.registry.session_ended(self.id);
+    }
+}
+
 /// Devices that need to can run sessions on the main thread.
 pub trait MainThreadSession: 'static {
     fn run_one_frame(&mut self);
@@ -455,8 +624,6 @@ where
 {
     fn run_one_frame(&mut self) {
         let frame_count = self.frame_count;
-        #[cfg(feature = "profile")]
-        let start_run = time::precise_time_ns();
         while frame_count == self.frame_count && self.running {
             if let Ok(msg) = crate::recv_timeout(&self.receiver, TIMEOUT) {
                 self.running = self.handle_msg(msg);
@@ -464,14 +631,6 @@ where
                 break;
             }
         }
-        #[cfg(feature = "profile")]
-        {
-            let end_run = time::precise_time_ns();
-            println!(
-                "WEBXR PROFILING [run_one_frame]:\t{}ms",
-                to_ms(end_run - start_run)
-            );
-        }
     }
 
     fn running(&self) -> bool {
@@ -485,6 +644,7 @@ pub struct SessionBuilder<'a, GL> {
     frame_sender: Sender<Frame>,
     layer_grand_manager: LayerGrandManager<GL>,
     id: SessionId,
+    registry: Registry,
 }
 
 impl<'a, GL: 'static> SessionBuilder<'a, GL> {
@@ -497,12 +657,14 @@ impl<'a, GL: 'static> SessionBuilder<'a, GL> {
         frame_sender: Sender<Frame>,
         layer_grand_manager: LayerGrandManager<GL>,
         id: SessionId,
+        registry: Registry,
     ) -> Self {
         SessionBuilder {
             sessions,
             frame_sender,
             layer_grand_manager,
             id,
+            registry,
         }
     }
 
@@ -516,9 +678,10 @@ impl<'a, GL: 'static> SessionBuilder<'a, GL> {
         let frame_sender = self.frame_sender;
         let layer_grand_manager = self.layer_grand_manager;
         let id = self.id;
+        let registry = self.registry;
         thread::spawn(move || {
             match factory(layer_grand_manager)
-                .and_then(|device| SessionThread::new(device, frame_sender, id))
+                .and_then(|device| SessionThread::new(device, frame_sender, id, registry))
             {
                 Ok(mut thread) => {
                     let session = thread.new_session();
@@ -541,7 +704,7 @@ impl<'a, GL: 'static> SessionBuilder<'a, GL> {
     {
         let device = factory(self.layer_grand_manager)?;
         let frame_sender = self.frame_sender;
-        let mut session_thread = SessionThread::new(device, frame_sender, self.id)?;
+        let mut session_thread = SessionThread::new(device, frame_sender, self.id, self.registry)?;
         let session = session_thread.new_session();
         self.sessions.push(Box::new(session_thread));
         Ok(session)
@@ -13,12 +13,42 @@ pub struct ApiSpace;
 pub enum BaseSpace {
     Local,
     Floor,
+    /// https://immersive-web.github.io/webxr/#dom-xrreferencespacetype-bounded-floor
+    /// The bounded floor space shares its origin with the floor space.
+    BoundedFloor,
+    /// https://immersive-web.github.io/webxr/#dom-xrreferencespacetype-unbounded
+    Unbounded,
     Viewer,
     TargetRay(InputId),
     Grip(InputId),
     Joint(InputId, Joint),
 }
 
+impl BaseSpace {
+    /// The type of reference space this is, if it is one
+    pub fn reference_space_type(self) -> Option<ReferenceSpaceType> {
+        match self {
+            BaseSpace::Local => Some(ReferenceSpaceType::Local),
+            BaseSpace::Floor => Some(ReferenceSpaceType::LocalFloor),
+            BaseSpace::BoundedFloor => Some(ReferenceSpaceType::BoundedFloor),
+            BaseSpace::Unbounded => Some(ReferenceSpaceType::Unbounded),
+            BaseSpace::Viewer => Some(ReferenceSpaceType::Viewer),
+            BaseSpace::TargetRay(_) | BaseSpace::Grip(_) | BaseSpace::Joint(..) => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
+/// https://immersive-web.github.io/webxr/#enumdef-xrreferencespacetype
+pub enum ReferenceSpaceType {
+    Viewer,
+    Local,
+    LocalFloor,
+    BoundedFloor,
+    Unbounded,
+}
+
 #[derive(Clone, Copy, Debug)]
 #[cfg_attr(feature = "ipc", derive(serde::Serialize, serde::Deserialize))]
 pub struct Space {
@@ -0,0 +1,77 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Statistics about how long frames take, collected by the session thread
+
+use std::collections::VecDeque;
+
+#[cfg(feature = "ipc")]
+use serde::{Deserialize, Serialize};
+
+/// How many frames of statistics we keep, if nobody takes them
+const HISTORY_LENGTH: usize = 256;
+
+/// The statistics for one trip around the render loop,
+/// which ends one frame and begins the next
+#[derive(Clone, Copy, Debug, Default)]
+#[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
+pub struct FrameStats {
+    /// The number of the frame which was ended
+    pub frame_count: u64,
+    /// How long the request to render the frame took to reach the session thread
+    pub raf_transit_ns: u64,
+    /// How long the device took to end the frame
+    pub end_frame_ns: u64,
+    /// How long the device took to begin the next frame
+    pub begin_frame_ns: u64,
+    /// When the device predicts the next frame will be displayed
+    pub predicted_display_time_ns: u64,
+    /// How many display refreshes were missed before the next frame.
+    /// The refresh period is estimated as the shortest time seen between frames.
+    pub missed_frames: u64,
+    /// How many layers were rendered in the frame
+    pub layer_count: usize,
+}
+
+/// The statistics which haven't been taken yet
+#[derive(Default)]
+pub(crate) struct FrameStatsHistory {
+    stats: VecDeque<FrameStats>,
+    last_display_time_ns: Option<u64>,
+    display_period_ns: Option<u64>,
+}
+
+impl FrameStatsHistory {
+    /// Estimate how many display refreshes were missed before
+    /// a frame which will be displayed at the given time
+    pub(crate) fn missed_frames(&mut self, display_time_ns: u64) -> u64 {
+        let last = self.last_display_time_ns.replace(display_time_ns);
+        let interval = match last {
+            Some(last) if display_time_ns > last => display_time_ns - last,
+            // The device clock may not advance, e.g. if it is paced manually
+            _ => return 0,
+        };
+        let period = match self.display_period_ns {
+            Some(period) if period <= interval => period,
+            _ => {
+                self.display_period_ns = Some(interval);
+                return 0;
+            }
+        };
+        (interval + period / 2) / period - 1
+    }
+
+    pub(crate) fn push(&mut self, stats: FrameStats) {
+        #[cfg(feature = "profile")]
+        log::info!(target: "webxr::frame_stats", "{:?}", stats);
+        if self.stats.len() == HISTORY_LENGTH {
+            self.stats.pop_front();
+        }
+        self.stats.push_back(stats);
+    }
+
+    pub(crate) fn take(&mut self) -> Vec<FrameStats> {
+        self.stats.drain(..).collect()
+    }
+}
@@ -1,6 +1,7 @@
 use crate::FrameUpdateEvent;
 use crate::HitTestId;
 use crate::HitTestSource;
+use crate::TransientHitTestSource;
 use euclid::Transform3D;
 
 #[derive(Clone, Copy, Debug)]
@@ -46,6 +47,8 @@ impl ClipPlanes {
 pub struct HitTestList {
     tests: Vec<HitTestSource>,
     uncommitted_tests: Vec<HitTestSource>,
+    transient_tests: Vec<TransientHitTestSource>,
+    uncommitted_transient_tests: Vec<TransientHitTestSource>,
 }
 
 impl HitTestList {
@@ -53,12 +56,20 @@ impl HitTestList {
         self.uncommitted_tests.push(source)
     }
 
+    pub fn request_hit_test_for_transient_input(&mut self, source: TransientHitTestSource) {
+        self.uncommitted_transient_tests.push(source)
+    }
+
     pub fn commit_tests(&mut self) -> Vec<FrameUpdateEvent> {
         let mut events = vec![];
         for test in self.uncommitted_tests.drain(..) {
             events.push(FrameUpdateEvent::HitTestSourceAdded(test.id));
             self.tests.push(test);
         }
+        for test in self.uncommitted_transient_tests.drain(..) {
+            events.push(FrameUpdateEvent::HitTestSourceAdded(test.id));
+            self.transient_tests.push(test);
+        }
         events
     }
 
@@ -66,9 +77,15 @@ impl HitTestList {
         &self.tests
     }
 
+    pub fn transient_tests(&self) -> &[TransientHitTestSource] {
+        &self.transient_tests
+    }
+
     pub fn cancel_hit_test(&mut self, id: HitTestId) {
         self.tests.retain(|s| s.id != id);
         self.uncommitted_tests.retain(|s| s.id != id);
+        self.transient_tests.retain(|s| s.id != id);
+        self.uncommitted_transient_tests.retain(|s| s.id != id);
     }
 }
 
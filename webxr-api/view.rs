@@ -163,7 +163,7 @@ pub enum Views {
 /// A list of viewports per-eye in the order of fields in Views.
 ///
 /// Not all must be in active use.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 #[cfg_attr(feature = "ipc", derive(Serialize, Deserialize))]
 pub struct Viewports {
     pub viewports: Vec<Rect<i32, Viewport>>,
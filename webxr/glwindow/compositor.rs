@@ -0,0 +1,493 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Compositing layers which aren't rendered from the viewer's point of view,
+//! such as quad, cylinder, equirect and cube layers, into the frame which is shown in the window.
+
+use euclid::Point2D;
+use euclid::Point3D;
+use euclid::Rect;
+use euclid::Size2D;
+use euclid::Transform3D;
+use euclid::UnknownUnit;
+
+use sparkle::gl;
+use sparkle::gl::GLuint;
+use sparkle::gl::Gl;
+
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::rc::Rc;
+
+use webxr_api::ApiSpace;
+use webxr_api::Display;
+use webxr_api::LayerId;
+use webxr_api::Viewport;
+
+const POSITION_ATTRIBUTE: GLuint = 0;
+const TEX_COORD_ATTRIBUTE: GLuint = 1;
+
+const LAYER_VERTEX_SHADER: &[u8] = b"
+  #version 330 core
+  layout(location=0) in vec4 position;
+  layout(location=1) in vec2 texCoord;
+  out vec2 vTexCoord;
+  void main(void) {
+    gl_Position = position;
+    vTexCoord = texCoord;
+  }
+";
+
+const LAYER_FRAGMENT_SHADER: &[u8] = b"
+  #version 330 core
+  layout(location=0) out vec4 color;
+  uniform sampler2D image;
+  in vec2 vTexCoord;
+  void main() {
+    color = texture(image, vTexCoord);
+  }
+";
+
+/// A vertex of a layer's geometry, in clip coordinates
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Vertex {
+    position: [f32; 4],
+    tex_coord: [f32; 2],
+}
+
+impl Vertex {
+    fn new(
+        matrix: &Transform3D<f32, ApiSpace, Display>,
+        point: Point3D<f32, ApiSpace>,
+        tex_coord: [f32; 2],
+    ) -> Vertex {
+        let position = matrix.transform_point3d_homogeneous(point);
+        Vertex {
+            position: [position.x, position.y, position.z, position.w],
+            tex_coord,
+        }
+    }
+}
+
+/// How many segments curved layers are split into, per radian
+const SEGMENTS_PER_RADIAN: f32 = 8.0;
+
+/// The shape of a layer which is placed in a space, in metres and radians
+#[derive(Clone, Copy, Debug)]
+pub enum Shape {
+    /// A rectangle centered on the origin, facing along the Z axis
+    Quad { width: f32, height: f32 },
+    /// Part of a cylinder around the Y axis, centered on the negative Z axis
+    Cylinder {
+        radius: f32,
+        central_angle: f32,
+        aspect_ratio: f32,
+    },
+    /// Part of a sphere, centered on the negative Z axis
+    Equirect {
+        radius: f32,
+        central_horizontal_angle: f32,
+        upper_vertical_angle: f32,
+        lower_vertical_angle: f32,
+    },
+    /// A cube centered on the origin, whose faces are the given distance from it
+    Cube { size: f32 },
+}
+
+/// The direction each face of a cube faces, and which ways are right and up in its texture,
+/// in the order +X, -X, +Y, -Y, +Z, -Z
+const CUBE_FACES: [[[f32; 3]; 3]; 6] = [
+    [[1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]],
+    [[-1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]],
+    [[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+    [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, -1.0]],
+    [[0.0, 0.0, 1.0], [-1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+    [[0.0, 0.0, -1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+];
+
+impl Shape {
+    /// The triangles of the shape, seen from inside, textured with parts of the layer's
+    /// texture, which are one for each face of a cube, or just one for other shapes
+    pub fn vertices(
+        &self,
+        matrix: &Transform3D<f32, ApiSpace, Display>,
+        tex_rects: &[Rect<f32, Viewport>],
+    ) -> Vec<Vertex> {
+        let tex_rect = match tex_rects.first() {
+            Some(tex_rect) => *tex_rect,
+            None => return Vec::new(),
+        };
+        match *self {
+            Shape::Quad { width, height } => grid_vertices(matrix, tex_rect, 1, 1, |u, v| {
+                Point3D::new((u - 0.5) * width, (v - 0.5) * height, 0.0)
+            }),
+            Shape::Cylinder {
+                radius,
+                central_angle,
+                aspect_ratio,
+            } => {
+                let height = radius * central_angle / aspect_ratio;
+                let columns = segments(central_angle);
+                grid_vertices(matrix, tex_rect, columns, 1, |u, v| {
+                    let angle = (u - 0.5) * central_angle;
+                    Point3D::new(
+                        radius * angle.sin(),
+                        (v - 0.5) * height,
+                        -radius * angle.cos(),
+                    )
+                })
+            }
+            Shape::Equirect {
+                radius,
+                central_horizontal_angle,
+                upper_vertical_angle,
+                lower_vertical_angle,
+            } => {
+                let vertical_angle = upper_vertical_angle - lower_vertical_angle;
+                let columns = segments(central_horizontal_angle);
+                let rows = segments(vertical_angle);
+                grid_vertices(matrix, tex_rect, columns, rows, |u, v| {
+                    let longitude = (u - 0.5) * central_horizontal_angle;
+                    let latitude = lower_vertical_angle + v * vertical_angle;
+                    Point3D::new(
+                        radius * latitude.cos() * longitude.sin(),
+                        radius * latitude.sin(),
+                        -radius * latitude.cos() * longitude.cos(),
+                    )
+                })
+            }
+            Shape::Cube { size } => CUBE_FACES
+                .iter()
+                .zip(tex_rects)
+                .flat_map(|(&[forward, right, up], &tex_rect)| {
+                    grid_vertices(matrix, tex_rect, 1, 1, |u, v| {
+                        let (x, y) = (u * 2.0 - 1.0, v * 2.0 - 1.0);
+                        Point3D::new(
+                            size * (forward[0] + x * right[0] + y * up[0]),
+                            size * (forward[1] + x * right[1] + y * up[1]),
+                            size * (forward[2] + x * right[2] + y * up[2]),
+                        )
+                    })
+                })
+                .collect(),
+        }
+    }
+}
+
+fn segments(angle: f32) -> usize {
+    1.max((angle.abs() * SEGMENTS_PER_RADIAN).ceil() as usize)
+}
+
+/// The triangles of a grid of points, given as a function of their texture coordinates
+/// in the range 0 to 1, which are then mapped into part of the layer's texture
+fn grid_vertices<F>(
+    matrix: &Transform3D<f32, ApiSpace, Display>,
+    tex_rect: Rect<f32, Viewport>,
+    columns: usize,
+    rows: usize,
+    point: F,
+) -> Vec<Vertex>
+where
+    F: Fn(f32, f32) -> Point3D<f32, ApiSpace>,
+{
+    let vertex = |column: usize, row: usize| {
+        let u = column as f32 / columns as f32;
+        let v = row as f32 / rows as f32;
+        let tex_coord = [
+            tex_rect.min_x() + u * tex_rect.size.width,
+            tex_rect.min_y() + v * tex_rect.size.height,
+        ];
+        Vertex::new(matrix, point(u, v), tex_coord)
+    };
+    let mut vertices = Vec::with_capacity(columns * rows * 6);
+    for row in 0..rows {
+        for column in 0..columns {
+            let bottom_left = vertex(column, row);
+            let bottom_right = vertex(column + 1, row);
+            let top_right = vertex(column + 1, row + 1);
+            let top_left = vertex(column, row + 1);
+            vertices.extend_from_slice(&[
+                bottom_left,
+                bottom_right,
+                top_right,
+                bottom_left,
+                top_right,
+                top_left,
+            ]);
+        }
+    }
+    vertices
+}
+
+/// Composites the layers of a frame into a texture, which is then shown in the window
+pub struct Compositor {
+    gl: Rc<Gl>,
+    framebuffer: GLuint,
+    texture: GLuint,
+    size: Size2D<i32, Viewport>,
+    buffer: GLuint,
+    vao: GLuint,
+    program: GLuint,
+    /// The framebuffers used to copy textures, for reading and drawing
+    copy_framebuffers: Vec<GLuint>,
+    /// Copies of the textures of static layers, which are only drawn once
+    static_textures: HashMap<LayerId, (GLuint, Size2D<i32, UnknownUnit>)>,
+}
+
+impl Compositor {
+    pub fn new(gl: Rc<Gl>) -> Compositor {
+        let framebuffer = gl.gen_framebuffers(1)[0];
+        let texture = gl.gen_textures(1)[0];
+        let buffer = gl.gen_buffers(1)[0];
+        let vao = gl.gen_vertex_arrays(1)[0];
+        let copy_framebuffers = gl.gen_framebuffers(2);
+
+        let program = gl.create_program();
+        let vertex_shader = gl.create_shader(gl::VERTEX_SHADER);
+        let fragment_shader = gl.create_shader(gl::FRAGMENT_SHADER);
+        gl.shader_source(vertex_shader, &[LAYER_VERTEX_SHADER]);
+        gl.compile_shader(vertex_shader);
+        gl.attach_shader(program, vertex_shader);
+        gl.shader_source(fragment_shader, &[LAYER_FRAGMENT_SHADER]);
+        gl.compile_shader(fragment_shader);
+        gl.attach_shader(program, fragment_shader);
+        gl.link_program(program);
+        gl.delete_shader(vertex_shader);
+        gl.delete_shader(fragment_shader);
+
+        let mut status = [0];
+        unsafe { gl.get_program_iv(program, gl::LINK_STATUS, &mut status) };
+        if status[0] != gl::TRUE as i32 {
+            log::warn!(
+                "Failed to link layer shader: {}",
+                gl.get_program_info_log(program)
+            );
+        }
+        debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
+
+        Compositor {
+            gl,
+            framebuffer,
+            texture,
+            size: Size2D::zero(),
+            buffer,
+            vao,
+            program,
+            copy_framebuffers,
+            static_textures: HashMap::new(),
+        }
+    }
+
+    /// Start compositing a frame of the given size, binding the framebuffer to draw to
+    pub fn begin(&mut self, size: Size2D<i32, Viewport>) {
+        if self.size != size {
+            self.gl.bind_texture(gl::TEXTURE_2D, self.texture);
+            self.gl.tex_image_2d(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA8 as _,
+                size.width,
+                size.height,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                gl::TexImageSource::Pixels(None),
+            );
+            self.gl
+                .tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as _);
+            self.gl
+                .tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as _);
+            self.gl.bind_framebuffer(gl::FRAMEBUFFER, self.framebuffer);
+            self.gl.framebuffer_texture_2d(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                self.texture,
+                0,
+            );
+            self.size = size;
+        }
+        self.gl.bind_framebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        debug_assert_eq!(
+            (
+                self.gl.get_error(),
+                self.gl.check_framebuffer_status(gl::FRAMEBUFFER)
+            ),
+            (gl::NO_ERROR, gl::FRAMEBUFFER_COMPLETE)
+        );
+        self.gl.clear_color(0.2, 0.3, 0.3, 1.0);
+        self.gl.clear(gl::COLOR_BUFFER_BIT);
+    }
+
+    /// Keep a copy of a static layer's texture, which it only gets the first time it is drawn
+    pub fn retain_texture(
+        &mut self,
+        layer_id: LayerId,
+        texture_id: GLuint,
+        texture_target: GLuint,
+        texture_size: Size2D<i32, UnknownUnit>,
+    ) {
+        let copy = self.gl.gen_textures(1)[0];
+        self.gl.bind_texture(gl::TEXTURE_2D, copy);
+        self.gl.tex_image_2d(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA8 as _,
+            texture_size.width,
+            texture_size.height,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            gl::TexImageSource::Pixels(None),
+        );
+        self.gl
+            .tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as _);
+        self.gl
+            .tex_parameter_i(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as _);
+        self.gl
+            .bind_framebuffer(gl::READ_FRAMEBUFFER, self.copy_framebuffers[0]);
+        self.gl.framebuffer_texture_2d(
+            gl::READ_FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            texture_target,
+            texture_id,
+            0,
+        );
+        self.gl
+            .bind_framebuffer(gl::DRAW_FRAMEBUFFER, self.copy_framebuffers[1]);
+        self.gl.framebuffer_texture_2d(
+            gl::DRAW_FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            gl::TEXTURE_2D,
+            copy,
+            0,
+        );
+        self.gl.blit_framebuffer(
+            0,
+            0,
+            texture_size.width,
+            texture_size.height,
+            0,
+            0,
+            texture_size.width,
+            texture_size.height,
+            gl::COLOR_BUFFER_BIT,
+            gl::NEAREST,
+        );
+        self.gl.bind_framebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        debug_assert_eq!(self.gl.get_error(), gl::NO_ERROR);
+        if let Some((old, _)) = self.static_textures.insert(layer_id, (copy, texture_size)) {
+            self.gl.delete_textures(&[old]);
+        }
+    }
+
+    pub fn retained_texture(
+        &self,
+        layer_id: LayerId,
+    ) -> Option<(GLuint, Size2D<i32, UnknownUnit>)> {
+        self.static_textures.get(&layer_id).cloned()
+    }
+
+    pub fn release_texture(&mut self, layer_id: LayerId) {
+        if let Some((texture, _)) = self.static_textures.remove(&layer_id) {
+            self.gl.delete_textures(&[texture]);
+        }
+    }
+
+    pub fn texture(&self) -> GLuint {
+        self.texture
+    }
+
+    pub fn size(&self) -> Size2D<i32, Viewport> {
+        self.size
+    }
+
+    /// Draw a layer's texture over the whole frame
+    pub fn draw_texture(&self, texture_id: GLuint, texture_target: GLuint, alpha: bool) {
+        let clip_space = Transform3D::identity();
+        let tex_rect = Rect::new(Point2D::zero(), Size2D::new(1.0, 1.0));
+        let shape = Shape::Quad {
+            width: 2.0,
+            height: 2.0,
+        };
+        let vertices = shape.vertices(&clip_space, &[tex_rect]);
+        let viewport = Rect::new(Point2D::zero(), self.size);
+        self.draw_triangles(texture_id, texture_target, &vertices, viewport, alpha);
+    }
+
+    /// Draw triangles textured with a layer's texture into one of the frame's viewports.
+    /// Layer textures have premultiplied alpha, which is ignored unless `alpha` is set.
+    pub fn draw_triangles(
+        &self,
+        texture_id: GLuint,
+        texture_target: GLuint,
+        vertices: &[Vertex],
+        viewport: Rect<i32, Viewport>,
+        alpha: bool,
+    ) {
+        self.gl.bind_framebuffer(gl::FRAMEBUFFER, self.framebuffer);
+        self.gl.viewport(
+            viewport.origin.x,
+            viewport.origin.y,
+            viewport.size.width,
+            viewport.size.height,
+        );
+        if alpha {
+            self.gl.enable(gl::BLEND);
+            self.gl.blend_func(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+        }
+
+        self.gl.use_program(self.program);
+        self.gl.bind_vertex_array(self.vao);
+        self.gl.bind_buffer(gl::ARRAY_BUFFER, self.buffer);
+        unsafe {
+            self.gl.buffer_data(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(vertices) as isize,
+                vertices.as_ptr() as *const c_void,
+                gl::STREAM_DRAW,
+            )
+        };
+        let stride = std::mem::size_of::<Vertex>() as i32;
+        let tex_coord_offset = std::mem::size_of::<[f32; 4]>() as u32;
+        self.gl
+            .vertex_attrib_pointer(POSITION_ATTRIBUTE, 4, gl::FLOAT, false, stride, 0);
+        self.gl.vertex_attrib_pointer(
+            TEX_COORD_ATTRIBUTE,
+            2,
+            gl::FLOAT,
+            false,
+            stride,
+            tex_coord_offset,
+        );
+        self.gl.enable_vertex_attrib_array(POSITION_ATTRIBUTE);
+        self.gl.enable_vertex_attrib_array(TEX_COORD_ATTRIBUTE);
+
+        self.gl.active_texture(gl::TEXTURE0);
+        self.gl.bind_texture(texture_target, texture_id);
+        self.gl.draw_arrays(gl::TRIANGLES, 0, vertices.len() as i32);
+
+        self.gl.disable_vertex_attrib_array(POSITION_ATTRIBUTE);
+        self.gl.disable_vertex_attrib_array(TEX_COORD_ATTRIBUTE);
+        if alpha {
+            self.gl.disable(gl::BLEND);
+        }
+        debug_assert_eq!(self.gl.get_error(), gl::NO_ERROR);
+    }
+}
+
+impl Drop for Compositor {
+    fn drop(&mut self) {
+        self.gl.delete_framebuffers(&[self.framebuffer]);
+        self.gl.delete_framebuffers(&self.copy_framebuffers);
+        self.gl.delete_textures(&[self.texture]);
+        for (texture, _) in self.static_textures.values() {
+            self.gl.delete_textures(&[*texture]);
+        }
+        self.gl.delete_buffers(&[self.buffer]);
+        self.gl.delete_vertex_arrays(&[self.vao]);
+        self.gl.delete_program(self.program);
+    }
+}
@@ -5,6 +5,9 @@
 use crate::SurfmanGL;
 use crate::SurfmanLayerManager;
 
+use self::compositor::Compositor;
+use self::compositor::Shape;
+
 use euclid::Angle;
 use euclid::Point2D;
 use euclid::Rect;
@@ -19,6 +22,7 @@ use sparkle::gl;
 use sparkle::gl::GLuint;
 use sparkle::gl::Gl;
 
+use std::collections::HashMap;
 use std::ffi::c_void;
 use std::rc::Rc;
 
@@ -38,8 +42,12 @@ use surfman_chains::SwapChains;
 use surfman_chains::SwapChainsAPI;
 
 use webxr_api::util::ClipPlanes;
+use webxr_api::ApiSpace;
+use webxr_api::BaseSpace;
 use webxr_api::ContextId;
 use webxr_api::DeviceAPI;
+use webxr_api::DeviceCapabilities;
+use webxr_api::DeviceDescriptor;
 use webxr_api::DiscoveryAPI;
 use webxr_api::Display;
 use webxr_api::Error;
@@ -47,11 +55,13 @@ use webxr_api::Event;
 use webxr_api::EventBuffer;
 use webxr_api::Floor;
 use webxr_api::Frame;
+use webxr_api::FrameUpdateEvent;
 use webxr_api::InputSource;
 use webxr_api::LayerGrandManager;
 use webxr_api::LayerId;
 use webxr_api::LayerInit;
 use webxr_api::LayerManager;
+use webxr_api::LayerType;
 use webxr_api::Native;
 use webxr_api::Quitter;
 use webxr_api::Sender;
@@ -60,6 +70,7 @@ use webxr_api::SessionBuilder;
 use webxr_api::SessionInit;
 use webxr_api::SessionMode;
 use webxr_api::SomeEye;
+use webxr_api::Space;
 use webxr_api::View;
 use webxr_api::Viewer;
 use webxr_api::ViewerPose;
@@ -75,6 +86,8 @@ use webxr_api::LEFT_EYE;
 use webxr_api::RIGHT_EYE;
 use webxr_api::VIEWER;
 
+mod compositor;
+
 // How far off the ground are the viewer's eyes?
 const HEIGHT: f32 = 1.0;
 
@@ -171,6 +184,15 @@ impl DiscoveryAPI<SurfmanGL> for GlWindowDiscovery {
     fn supports_session(&self, mode: SessionMode) -> bool {
         mode == SessionMode::ImmersiveVR || mode == SessionMode::ImmersiveAR
     }
+
+    fn descriptor(&self) -> DeviceDescriptor {
+        DeviceDescriptor {
+            name: "GL window".into(),
+            vendor: "WebXR".into(),
+            modes: vec![SessionMode::ImmersiveVR, SessionMode::ImmersiveAR],
+            features: vec!["local-floor".into()],
+        }
+    }
 }
 
 pub struct GlWindowDevice {
@@ -187,6 +209,13 @@ pub struct GlWindowDevice {
     clip_planes: ClipPlanes,
     granted_features: Vec<String>,
     shader: Option<GlWindowShader>,
+    compositor: Compositor,
+    /// How each layer was created, which says how it is composited
+    layer_inits: HashMap<LayerId, LayerInit>,
+    /// The viewer's pose in the current frame
+    viewer: RigidTransform3D<f32, Viewer, Native>,
+    /// The viewports which the layer manager was last given
+    layer_viewports: Option<Viewports>,
 }
 
 impl DeviceAPI for GlWindowDevice {
@@ -217,10 +246,14 @@ impl DeviceAPI for GlWindowDevice {
     }
 
     fn create_layer(&mut self, context_id: ContextId, init: LayerInit) -> Result<LayerId, Error> {
-        self.layer_manager()?.create_layer(context_id, init)
+        let layer_id = self.layer_manager()?.create_layer(context_id, init)?;
+        self.layer_inits.insert(layer_id, init);
+        Ok(layer_id)
     }
 
     fn destroy_layer(&mut self, context_id: ContextId, layer_id: LayerId) {
+        self.layer_inits.remove(&layer_id);
+        self.compositor.release_texture(layer_id);
         self.layer_manager()
             .unwrap()
             .destroy_layer(context_id, layer_id)
@@ -235,6 +268,18 @@ impl DeviceAPI for GlWindowDevice {
         let rotation = Rotation3D::from_untyped(&self.window.get_rotation());
         let rotation = RigidTransform3D::from_rotation(rotation);
         let transform = translation.post_transform(&rotation);
+        self.viewer = transform;
+        // Resizing the window changes the viewports, so the layers are resized before the frame
+        let mut events = vec![];
+        let viewports = self.viewports();
+        if self.layer_viewports.is_some() && self.layer_viewports.as_ref() != Some(&viewports) {
+            self.layer_viewports = Some(viewports.clone());
+            let layer_manager = self.layer_manager().ok()?;
+            if let Err(err) = layer_manager.set_viewports(viewports.clone()) {
+                log::warn!("Failed to resize layers: {:?}", err);
+            }
+            events.push(FrameUpdateEvent::UpdateViewports(viewports));
+        }
         let sub_images = self.layer_manager().ok()?.begin_frame(layers).ok()?;
         Some(Frame {
             pose: Some(ViewerPose {
@@ -242,11 +287,13 @@ impl DeviceAPI for GlWindowDevice {
                 views: self.views(transform),
             }),
             inputs: vec![],
-            events: vec![],
+            events,
             time_ns,
             sub_images,
             sent_time: 0,
             hit_test_results: vec![],
+            transient_hit_test_results: vec![],
+            anchors: vec![],
         })
     }
 
@@ -259,28 +306,31 @@ impl DeviceAPI for GlWindowDevice {
 
         let window_size = self.window_size();
         let viewport_size = self.viewport_size();
-
-        let framebuffer_object = self
-            .device
-            .context_surface_info(&self.context)
-            .unwrap()
-            .map(|info| info.framebuffer_object)
-            .unwrap_or(0);
-        self.gl
-            .bind_framebuffer(gl::FRAMEBUFFER, framebuffer_object);
-        debug_assert_eq!(
-            (
-                self.gl.get_error(),
-                self.gl.check_framebuffer_status(gl::FRAMEBUFFER)
-            ),
-            (gl::NO_ERROR, gl::FRAMEBUFFER_COMPLETE)
-        );
-
-        self.gl.clear_color(0.2, 0.3, 0.3, 1.0);
-        self.gl.clear(gl::COLOR_BUFFER_BIT);
-        debug_assert_eq!(self.gl.get_error(), gl::NO_ERROR);
-
-        for &(_, layer_id) in layers {
+        let viewports = self.viewports().viewports;
+        let composite_size = viewports
+            .iter()
+            .fold(Rect::zero(), |union: Rect<i32, Viewport>, viewport| {
+                union.union(viewport)
+            })
+            .size;
+
+        // Composite the layers, in order but with cube layers as the background,
+        // and then show the result in the window
+        self.compositor.begin(composite_size);
+        let (background, foreground): (Vec<_>, Vec<_>) =
+            layers.iter().partition(|&&(_, layer_id)| {
+                let init = self.layer_inits.get(&layer_id);
+                init.map(LayerInit::layer_type) == Some(LayerType::Cube)
+            });
+        for (i, &(_, layer_id)) in background.into_iter().chain(foreground).enumerate() {
+            let init = match self.layer_inits.get(&layer_id) {
+                Some(init) => *init,
+                None => continue,
+            };
+            if let Some((texture_id, texture_size)) = self.compositor.retained_texture(layer_id) {
+                self.draw_layer(&init, i == 0, texture_id, gl::TEXTURE_2D, texture_size);
+                continue;
+            }
             let swap_chain = match self.swap_chains.get(layer_id) {
                 Some(swap_chain) => swap_chain,
                 None => continue,
@@ -296,19 +346,13 @@ impl DeviceAPI for GlWindowDevice {
                 .unwrap();
             let texture_id = self.device.surface_texture_object(&surface_texture);
             let texture_target = self.device.surface_gl_texture_target();
-            log::debug!("Presenting texture {}", texture_id);
-
-            if let Some(ref shader) = self.shader {
-                shader.draw_texture(
-                    texture_id,
-                    texture_target,
-                    texture_size,
-                    viewport_size,
-                    window_size,
-                );
-            } else {
-                self.blit_texture(texture_id, texture_target, texture_size, window_size);
+            log::debug!("Compositing texture {}", texture_id);
+
+            if init.is_static() {
+                self.compositor
+                    .retain_texture(layer_id, texture_id, texture_target, texture_size);
             }
+            self.draw_layer(&init, i == 0, texture_id, texture_target, texture_size);
             debug_assert_eq!(self.gl.get_error(), gl::NO_ERROR);
 
             let surface = self
@@ -318,6 +362,42 @@ impl DeviceAPI for GlWindowDevice {
             swap_chain.recycle_surface(surface);
         }
 
+        let framebuffer_object = self
+            .device
+            .context_surface_info(&self.context)
+            .unwrap()
+            .map(|info| info.framebuffer_object)
+            .unwrap_or(0);
+        self.gl
+            .bind_framebuffer(gl::FRAMEBUFFER, framebuffer_object);
+        debug_assert_eq!(
+            (
+                self.gl.get_error(),
+                self.gl.check_framebuffer_status(gl::FRAMEBUFFER)
+            ),
+            (gl::NO_ERROR, gl::FRAMEBUFFER_COMPLETE)
+        );
+
+        self.gl.clear_color(0.2, 0.3, 0.3, 1.0);
+        self.gl.clear(gl::COLOR_BUFFER_BIT);
+        debug_assert_eq!(self.gl.get_error(), gl::NO_ERROR);
+
+        let texture_id = self.compositor.texture();
+        let texture_size = self.compositor.size().to_untyped();
+        log::debug!("Presenting texture {}", texture_id);
+        if let Some(ref shader) = self.shader {
+            shader.draw_texture(
+                texture_id,
+                gl::TEXTURE_2D,
+                texture_size,
+                viewport_size,
+                window_size,
+            );
+        } else {
+            self.blit_texture(texture_id, gl::TEXTURE_2D, texture_size, window_size);
+        }
+        debug_assert_eq!(self.gl.get_error(), gl::NO_ERROR);
+
         match self.target_swap_chain.as_ref() {
             Some(target_swap_chain) => {
                 // Rendering to a surfman swap chain
@@ -369,6 +449,19 @@ impl DeviceAPI for GlWindowDevice {
     fn granted_features(&self) -> &[String] {
         &self.granted_features
     }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut capabilities = DeviceCapabilities::new(self.granted_features.clone(), true);
+        capabilities.layer_types.extend(&[
+            LayerType::Quad,
+            LayerType::Cylinder,
+            LayerType::Equirect,
+            LayerType::Cube,
+        ]);
+        // The surfman layer manager copies texture arrays into the textures we composite
+        capabilities.texture_arrays = true;
+        capabilities
+    }
 }
 
 impl Drop for GlWindowDevice {
@@ -436,6 +529,7 @@ impl GlWindowDevice {
         let layer_manager = None;
 
         let shader = GlWindowShader::new(gl.clone(), window.get_mode());
+        let compositor = Compositor::new(gl.clone());
         debug_assert_eq!(gl.get_error(), gl::NO_ERROR);
 
         Ok(GlWindowDevice {
@@ -452,9 +546,172 @@ impl GlWindowDevice {
             clip_planes: Default::default(),
             granted_features,
             shader,
+            compositor,
+            layer_inits: HashMap::new(),
+            viewer: RigidTransform3D::identity(),
+            layer_viewports: None,
         })
     }
 
+    /// Draw a layer into the frame. Layers which are placed in a space are drawn
+    /// from the point of view of each view, and the others are drawn over the whole frame.
+    fn draw_layer(
+        &self,
+        init: &LayerInit,
+        first: bool,
+        texture_id: GLuint,
+        texture_target: GLuint,
+        texture_size: Size2D<i32, UnknownUnit>,
+    ) {
+        let (space, shape, alpha) = match *init {
+            LayerInit::QuadLayer {
+                space,
+                width,
+                height,
+                alpha,
+                ..
+            } => (space, Shape::Quad { width, height }, alpha),
+            LayerInit::CylinderLayer {
+                space,
+                radius,
+                central_angle,
+                aspect_ratio,
+                alpha,
+                ..
+            } => {
+                let shape = Shape::Cylinder {
+                    radius,
+                    central_angle,
+                    aspect_ratio,
+                };
+                (space, shape, alpha)
+            }
+            LayerInit::EquirectLayer {
+                space,
+                radius,
+                central_horizontal_angle,
+                upper_vertical_angle,
+                lower_vertical_angle,
+                alpha,
+                ..
+            } => {
+                // An equirect layer with no radius is infinitely far away, which
+                // we draw as a sphere around the viewer, just inside the far plane
+                let radius = if radius > 0.0 {
+                    radius
+                } else {
+                    self.clip_planes.far / 2.0
+                };
+                let shape = Shape::Equirect {
+                    radius,
+                    central_horizontal_angle,
+                    upper_vertical_angle,
+                    lower_vertical_angle,
+                };
+                (space, shape, alpha)
+            }
+            LayerInit::CubeLayer { space, alpha, .. } => {
+                // Cube layers are infinitely far away, so we draw them just inside the far plane
+                let shape = Shape::Cube {
+                    size: self.clip_planes.far / 2.0,
+                };
+                (space, shape, alpha)
+            }
+            LayerInit::WebGLLayer { .. } | LayerInit::ProjectionLayer { .. } => {
+                // The bottom layer is opaque, so it can just be copied into the frame
+                if first || !init.alpha() {
+                    let composite_size = self.compositor.size();
+                    self.blit_texture(texture_id, texture_target, texture_size, composite_size);
+                } else {
+                    self.compositor
+                        .draw_texture(texture_id, texture_target, true);
+                }
+                return;
+            }
+        };
+        let mut origin = match self.native_origin(space) {
+            Some(origin) => origin,
+            None => return,
+        };
+        let infinite = match *init {
+            LayerInit::EquirectLayer { radius, .. } => radius <= 0.0,
+            LayerInit::CubeLayer { .. } => true,
+            _ => false,
+        };
+        if infinite {
+            // Layers which are infinitely far away move with the viewer
+            origin = RigidTransform3D::new(origin.rotation, self.viewer.translation);
+        }
+        let origin = origin.to_transform();
+        let view_matrices = self.view_matrices();
+        let viewports = self.viewports().viewports;
+        let layer_viewports = init.view_viewports().unwrap_or_default();
+        let views_per_eye = match shape {
+            Shape::Cube { .. } => 6,
+            _ => 1,
+        };
+        let eyes: Vec<_> = layer_viewports.chunks(views_per_eye).collect();
+        let texture_size = texture_size.to_f32();
+        for (i, (view_matrix, viewport)) in view_matrices.iter().zip(&viewports).enumerate() {
+            // Stereo layers show each eye its own half of the texture, but if the
+            // device doesn't have one view per eye, every view gets the left eye
+            let eye = if eyes.len() == view_matrices.len() {
+                i
+            } else {
+                0
+            };
+            let tex_rects: Vec<_> = match eyes.get(eye) {
+                Some(eye_viewports) => eye_viewports
+                    .iter()
+                    .map(|layer_viewport| {
+                        layer_viewport
+                            .to_f32()
+                            .scale(1.0 / texture_size.width, 1.0 / texture_size.height)
+                    })
+                    .collect(),
+                None => continue,
+            };
+            let matrix = origin.post_transform(view_matrix);
+            let vertices = shape.vertices(&matrix, &tex_rects);
+            self.compositor
+                .draw_triangles(texture_id, texture_target, &vertices, *viewport, alpha);
+        }
+    }
+
+    fn native_origin(&self, space: Space) -> Option<RigidTransform3D<f32, ApiSpace, Native>> {
+        let origin: RigidTransform3D<f32, ApiSpace, Native> = match space.base {
+            BaseSpace::Local | BaseSpace::Unbounded => RigidTransform3D::identity(),
+            BaseSpace::Floor | BaseSpace::BoundedFloor => {
+                self.floor_transform()?.inverse().cast_unit()
+            }
+            BaseSpace::Viewer => self.viewer.cast_unit(),
+            BaseSpace::TargetRay(_) | BaseSpace::Grip(_) | BaseSpace::Joint(..) => return None,
+        };
+        Some(origin.pre_transform(&space.offset))
+    }
+
+    /// The matrices from native space to clip space of each view, in the order of the viewports
+    fn view_matrices(&self) -> Vec<Transform3D<f32, Native, Display>> {
+        fn view_matrix<Eye>(view: &View<Eye>) -> Transform3D<f32, Native, Display> {
+            view.transform
+                .inverse()
+                .to_transform()
+                .post_transform(&view.projection)
+        }
+        match self.views(self.viewer) {
+            Views::Stereo(left, right) => vec![view_matrix(&left), view_matrix(&right)],
+            Views::Cubemap(front, left, right, top, bottom, back) => vec![
+                view_matrix(&front),
+                view_matrix(&left),
+                view_matrix(&right),
+                view_matrix(&top),
+                view_matrix(&bottom),
+                view_matrix(&back),
+            ],
+            _ => vec![],
+        }
+    }
+
     fn blit_texture(
         &self,
         texture_id: GLuint,
@@ -491,6 +748,7 @@ impl GlWindowDevice {
         }
         let swap_chains = self.swap_chains.clone();
         let viewports = self.viewports();
+        self.layer_viewports = Some(viewports.clone());
         let layer_manager = self.grand_manager.create_layer_manager(move |_, _| {
             Ok(SurfmanLayerManager::new(viewports, swap_chains))
         })?;
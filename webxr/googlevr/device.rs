@@ -614,6 +614,7 @@ impl GoogleVRDevice {
                 pressed: false,
                 squeezed: false,
                 hand: None,
+                gamepad: None,
             }]
         } else {
             vec![]
@@ -668,6 +669,8 @@ impl DeviceAPI for GoogleVRDevice {
             sub_images,
             sent_time: 0,
             hit_test_results: vec![],
+            transient_hit_test_results: vec![],
+            anchors: vec![],
         })
     }
 
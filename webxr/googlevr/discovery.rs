@@ -4,6 +4,7 @@
 
 use crate::surfman_layer_manager::SurfmanGL;
 
+use webxr_api::DeviceDescriptor;
 use webxr_api::DiscoveryAPI;
 use webxr_api::Error;
 use webxr_api::Session;
@@ -125,6 +126,15 @@ impl DiscoveryAPI<SurfmanGL> for GoogleVRDiscovery {
     fn supports_session(&self, mode: SessionMode) -> bool {
         mode == SessionMode::ImmersiveVR
     }
+
+    fn descriptor(&self) -> DeviceDescriptor {
+        DeviceDescriptor {
+            name: "Google VR".into(),
+            vendor: "Google".into(),
+            modes: vec![SessionMode::ImmersiveVR],
+            features: vec![],
+        }
+    }
 }
 
 impl GoogleVRDiscovery {
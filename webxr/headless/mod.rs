@@ -5,18 +5,32 @@
 use crate::SurfmanGL;
 use crate::SurfmanLayerManager;
 
+use self::world::DetectedMeshes;
+use self::world::DetectedPlanes;
+
 use webxr_api::util::{self, ClipPlanes, HitTestList};
+use webxr_api::AnchorId;
+use webxr_api::AnchorPose;
+use webxr_api::AnchorSource;
+use webxr_api::AnchorSpace;
 use webxr_api::ApiSpace;
 use webxr_api::BaseSpace;
 use webxr_api::ContextId;
 use webxr_api::DeviceAPI;
+use webxr_api::DeviceCapabilities;
+use webxr_api::DeviceDescriptor;
+use webxr_api::DeviceNotifier;
 use webxr_api::DiscoveryAPI;
+use webxr_api::EntityTypes;
 use webxr_api::Error;
 use webxr_api::Event;
 use webxr_api::EventBuffer;
 use webxr_api::Floor;
 use webxr_api::Frame;
 use webxr_api::FrameUpdateEvent;
+use webxr_api::Gamepad;
+use webxr_api::GestureRecognizer;
+use webxr_api::Hand;
 use webxr_api::HitTestId;
 use webxr_api::HitTestResult;
 use webxr_api::HitTestSource;
@@ -24,18 +38,23 @@ use webxr_api::Input;
 use webxr_api::InputFrame;
 use webxr_api::InputId;
 use webxr_api::InputSource;
+use webxr_api::JointFrame;
 use webxr_api::LayerGrandManager;
 use webxr_api::LayerId;
 use webxr_api::LayerInit;
 use webxr_api::LayerManager;
+use webxr_api::LayerType;
+use webxr_api::Mesh;
 use webxr_api::MockDeviceInit;
 use webxr_api::MockDeviceMsg;
 use webxr_api::MockDiscoveryAPI;
+use webxr_api::MockFramePacing;
 use webxr_api::MockInputMsg;
 use webxr_api::MockViewInit;
 use webxr_api::MockViewsInit;
 use webxr_api::MockWorld;
 use webxr_api::Native;
+use webxr_api::Plane;
 use webxr_api::Quitter;
 use webxr_api::Ray;
 use webxr_api::Receiver;
@@ -48,23 +67,35 @@ use webxr_api::SessionInit;
 use webxr_api::SessionMode;
 use webxr_api::Space;
 use webxr_api::SubImages;
+use webxr_api::TargetRayMode;
+use webxr_api::TransientHitTestResult;
+use webxr_api::TransientHitTestSource;
 use webxr_api::View;
 use webxr_api::Viewer;
 use webxr_api::ViewerPose;
 use webxr_api::Viewports;
 use webxr_api::Views;
+use webxr_api::Visibility;
 
+use euclid::Angle;
+use euclid::Point3D;
 use euclid::RigidTransform3D;
+use euclid::Rotation3D;
+use euclid::Vector3D;
 
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
+use std::time::Duration;
 
 use surfman_chains::SwapChains;
 
+mod world;
+
 pub struct HeadlessMockDiscovery {}
 
 struct HeadlessDiscovery {
     data: Arc<Mutex<HeadlessDeviceData>>,
+    clock: Arc<Condvar>,
     supports_vr: bool,
     supports_inline: bool,
     supports_ar: bool,
@@ -75,13 +106,37 @@ struct InputInfo {
     active: bool,
     pointer: Option<RigidTransform3D<f32, Input, Native>>,
     grip: Option<RigidTransform3D<f32, Input, Native>>,
+    hand: Option<Box<Hand<JointFrame>>>,
+    gestures: GestureRecognizer,
+    gamepad: Option<Gamepad>,
     clicking: bool,
 }
 
+/// An anchor, which is kept fixed in native space
+struct Anchor {
+    id: AnchorId,
+    pose: RigidTransform3D<f32, AnchorSpace, Native>,
+    /// Whether the anchor is attached to the surface of the world,
+    /// in which case it is lost when the surface goes away
+    attached: bool,
+}
+
 struct HeadlessDevice {
     data: Arc<Mutex<HeadlessDeviceData>>,
+    /// Notified when the device clock changes
+    clock: Arc<Condvar>,
     id: u32,
+    /// The real time at which the current frame began
+    frame_start_ns: u64,
+    /// The device time of the current frame
+    frame_time_ns: u64,
     hit_tests: HitTestList,
+    anchors: Vec<Anchor>,
+    next_anchor_id: u32,
+    /// The planes as last reported to the session
+    reported_planes: Vec<Plane>,
+    /// The meshes as last reported to the session
+    reported_meshes: Vec<Mesh>,
     granted_features: Vec<String>,
     grand_manager: LayerGrandManager<SurfmanGL>,
     layer_manager: Option<LayerManager>,
@@ -94,10 +149,18 @@ struct PerSessionData {
     quitter: Option<Quitter>,
     events: EventBuffer,
     needs_vp_update: bool,
+    needs_bounds_update: bool,
+    /// Whether the session has focus, and so should be sent input events
+    visibility: Visibility,
+    /// How native space has moved since the last frame, because the device was recentered
+    native_reset: Option<RigidTransform3D<f32, Native, Native>>,
+    /// Whether the session is ending, so its frames should stop waiting for the clock
+    ending: bool,
 }
 
 struct HeadlessDeviceData {
     floor_transform: Option<RigidTransform3D<f32, Native, Floor>>,
+    bounds_geometry: Option<Vec<Point3D<f32, Floor>>>,
     viewer_origin: Option<RigidTransform3D<f32, Viewer, Native>>,
     supported_features: Vec<String>,
     views: MockViewsInit,
@@ -105,8 +168,19 @@ struct HeadlessDeviceData {
     inputs: Vec<InputInfo>,
     sessions: Vec<PerSessionData>,
     disconnected: bool,
+    /// Tells the registry when the device is disconnected
+    notifier: Option<DeviceNotifier>,
     world: Option<MockWorld>,
+    planes: DetectedPlanes,
+    meshes: DetectedMeshes,
     next_id: u32,
+    /// The number of frames begun by immersive sessions, which the timeline is keyed by
+    frame_count: u64,
+    timeline: Vec<(u64, MockDeviceMsg)>,
+    frame_pacing: MockFramePacing,
+    /// The device clock, as it was at the real time `clock_synced_ns`
+    clock_ns: u64,
+    clock_synced_ns: u64,
 }
 
 impl MockDiscoveryAPI<SurfmanGL> for HeadlessMockDiscovery {
@@ -118,8 +192,9 @@ impl MockDiscoveryAPI<SurfmanGL> for HeadlessMockDiscovery {
         let viewer_origin = init.viewer_origin.clone();
         let floor_transform = init.floor_origin.map(|f| f.inverse());
         let views = init.views.clone();
-        let data = HeadlessDeviceData {
+        let mut data = HeadlessDeviceData {
             floor_transform,
+            bounds_geometry: init.bounds_geometry,
             viewer_origin,
             supported_features: init.supported_features,
             views,
@@ -127,17 +202,29 @@ impl MockDiscoveryAPI<SurfmanGL> for HeadlessMockDiscovery {
             inputs: vec![],
             sessions: vec![],
             disconnected: false,
+            notifier: None,
             world: init.world,
+            planes: Default::default(),
+            meshes: Default::default(),
             next_id: 0,
+            frame_count: 0,
+            timeline: vec![],
+            frame_pacing: Default::default(),
+            clock_ns: time::precise_time_ns(),
+            clock_synced_ns: time::precise_time_ns(),
         };
+        data.update_detections();
         let data = Arc::new(Mutex::new(data));
         let data_ = data.clone();
+        let clock = Arc::new(Condvar::new());
+        let clock_ = clock.clone();
 
         thread::spawn(move || {
-            run_loop(receiver, data_);
+            run_loop(receiver, data_, clock_);
         });
         Ok(Box::new(HeadlessDiscovery {
             data,
+            clock,
             supports_vr: init.supports_vr,
             supports_inline: init.supports_inline,
             supports_ar: init.supports_ar,
@@ -145,9 +232,19 @@ impl MockDiscoveryAPI<SurfmanGL> for HeadlessMockDiscovery {
     }
 }
 
-fn run_loop(receiver: Receiver<MockDeviceMsg>, data: Arc<Mutex<HeadlessDeviceData>>) {
+fn run_loop(
+    receiver: Receiver<MockDeviceMsg>,
+    data: Arc<Mutex<HeadlessDeviceData>>,
+    clock: Arc<Condvar>,
+) {
     while let Ok(msg) = receiver.recv() {
-        if !data.lock().expect("Mutex poisoned").handle_msg(msg) {
+        let mut d = data.lock().expect("Mutex poisoned");
+        // The device may also have been disconnected by the timeline
+        let running = d.handle_msg(msg) && !d.disconnected;
+        drop(d);
+        // Wake up any sessions waiting for the clock to advance
+        clock.notify_all();
+        if !running {
             break;
         }
     }
@@ -160,10 +257,14 @@ impl DiscoveryAPI<SurfmanGL> for HeadlessDiscovery {
         init: &SessionInit,
         xr: SessionBuilder<SurfmanGL>,
     ) -> Result<Session, Error> {
+        if self.data.lock().unwrap().disconnected {
+            return Err(Error::DeviceLost);
+        }
         if !self.supports_session(mode) {
             return Err(Error::NoMatchingDevice);
         }
         let data = self.data.clone();
+        let clock = self.clock.clone();
         let mut d = data.lock().unwrap();
         let id = d.next_id;
         d.next_id += 1;
@@ -174,6 +275,10 @@ impl DiscoveryAPI<SurfmanGL> for HeadlessDiscovery {
             quitter: Default::default(),
             events: Default::default(),
             needs_vp_update: false,
+            needs_bounds_update: false,
+            visibility: Visibility::Visible,
+            native_reset: None,
+            ending: false,
         };
         d.sessions.push(per_session);
 
@@ -183,9 +288,16 @@ impl DiscoveryAPI<SurfmanGL> for HeadlessDiscovery {
         xr.spawn(move |grand_manager| {
             Ok(HeadlessDevice {
                 data,
+                clock,
                 id,
+                frame_start_ns: 0,
+                frame_time_ns: 0,
                 granted_features,
                 hit_tests: HitTestList::default(),
+                anchors: vec![],
+                next_anchor_id: 0,
+                reported_planes: vec![],
+                reported_meshes: vec![],
                 grand_manager,
                 layer_manager,
             })
@@ -202,6 +314,28 @@ impl DiscoveryAPI<SurfmanGL> for HeadlessDiscovery {
             SessionMode::ImmersiveAR => self.supports_ar,
         }
     }
+
+    fn set_device_notifier(&mut self, notifier: DeviceNotifier) {
+        self.data.lock().unwrap().notifier = Some(notifier);
+    }
+
+    fn descriptor(&self) -> DeviceDescriptor {
+        let modes = [
+            (SessionMode::Inline, self.supports_inline),
+            (SessionMode::ImmersiveVR, self.supports_vr),
+            (SessionMode::ImmersiveAR, self.supports_ar),
+        ];
+        DeviceDescriptor {
+            name: "Headless mock device".into(),
+            vendor: "WebXR".into(),
+            modes: modes
+                .iter()
+                .filter(|&&(_, supported)| supported)
+                .map(|&(mode, _)| mode)
+                .collect(),
+            features: self.data.lock().unwrap().supported_features.clone(),
+        }
+    }
 }
 
 fn view<Eye>(
@@ -252,6 +386,10 @@ impl DeviceAPI for HeadlessDevice {
         self.data.lock().unwrap().floor_transform.clone()
     }
 
+    fn bounds_geometry(&self) -> Option<Vec<Point3D<f32, Floor>>> {
+        self.data.lock().unwrap().bounds_geometry.clone()
+    }
+
     fn viewports(&self) -> Viewports {
         let d = self.data.lock().unwrap();
         let per_session = d.sessions.iter().find(|s| s.id == self.id).unwrap();
@@ -269,52 +407,154 @@ impl DeviceAPI for HeadlessDevice {
     }
 
     fn begin_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) -> Option<Frame> {
+        // Layers are resized between frames, so that the frame's sub images match its views.
+        // The timeline is applied first, since it may change the views.
+        let viewports = {
+            let mut data = self.data.lock().unwrap();
+            let mode = data.sessions.iter().find(|s| s.id == self.id).unwrap().mode;
+            // Only the frames of immersive sessions drive the timeline
+            if mode != SessionMode::Inline && !data.apply_timeline() {
+                // The timeline disconnected the device, which ends the session
+                self.clock.notify_all();
+                return None;
+            }
+            self.clock.notify_all();
+            data.recognize_gestures();
+            let per_session = data.sessions.iter_mut().find(|s| s.id == self.id).unwrap();
+            if per_session.needs_vp_update {
+                per_session.needs_vp_update = false;
+                Some(data.viewports(mode))
+            } else {
+                None
+            }
+        };
+        if let Some(ref viewports) = viewports {
+            let layer_manager = self.layer_manager().ok()?;
+            if let Err(err) = layer_manager.set_viewports(viewports.clone()) {
+                log::warn!("Failed to resize layers: {:?}", err);
+            }
+        }
         let sub_images = self.layer_manager().ok()?.begin_frame(layers).ok()?;
         let mut data = self.data.lock().unwrap();
         let mut frame = data.get_frame(
             data.sessions.iter().find(|s| s.id == self.id).unwrap(),
             sub_images,
         );
+        if let Some(viewports) = viewports {
+            frame
+                .events
+                .push(FrameUpdateEvent::UpdateViewports(viewports));
+        }
+        frame.events.extend(self.hit_tests.commit_tests());
+
         let per_session = data.sessions.iter_mut().find(|s| s.id == self.id).unwrap();
-        if per_session.needs_vp_update {
-            per_session.needs_vp_update = false;
-            let mode = per_session.mode;
-            let vp = data.viewports(mode);
-            frame.events.push(FrameUpdateEvent::UpdateViewports(vp));
+        if per_session.needs_bounds_update {
+            per_session.needs_bounds_update = false;
+            let bounds = data.bounds_geometry.clone();
+            frame
+                .events
+                .push(FrameUpdateEvent::UpdateBoundsGeometry(bounds));
+        }
+        if let Some(reset) = per_session.native_reset.take() {
+            // Anchors stay put in the real world, so they move in native space
+            for anchor in &mut self.anchors {
+                anchor.pose = anchor.pose.post_transform(&reset);
+            }
+        }
+
+        for source in self.hit_tests.tests() {
+            let results = data.hit_test(source.id, source.ray, source.space, source.types);
+            frame.hit_test_results.extend(results);
         }
-        let events = self.hit_tests.commit_tests();
-        frame.events = events;
-
-        if let Some(ref world) = data.world {
-            for source in self.hit_tests.tests() {
-                let ray = data.native_ray(source.ray, source.space);
-                let ray = if let Some(ray) = ray { ray } else { break };
-                let hits = world
-                    .regions
-                    .iter()
-                    .filter(|region| source.types.is_type(region.ty))
-                    .flat_map(|region| &region.faces)
-                    .filter_map(|triangle| triangle.intersect(ray))
-                    .map(|space| HitTestResult {
-                        space,
+        for source in self.hit_tests.transient_tests() {
+            // Transient inputs are the screen inputs, e.g. taps on a phone
+            let inputs = data.inputs.iter().filter(|i| {
+                i.active
+                    && i.source.profiles.contains(&source.profile)
+                    && matches!(i.source.target_ray_mode, TargetRayMode::Screen)
+            });
+            for input in inputs {
+                let space = Space {
+                    base: BaseSpace::TargetRay(input.source.id),
+                    offset: RigidTransform3D::identity(),
+                };
+                let results = data.hit_test(source.id, source.ray, space, source.types);
+                frame
+                    .transient_hit_test_results
+                    .push(TransientHitTestResult {
                         id: source.id,
+                        input: input.source.id,
+                        results,
                     });
-                frame.hit_test_results.extend(hits);
             }
         }
 
+        let world = data.world.as_ref();
+        let mut lost = vec![];
+        self.anchors.retain(|anchor| {
+            let tracked = !anchor.attached || world.map_or(false, |w| on_surface(w, anchor.pose));
+            if !tracked {
+                lost.push(anchor.id);
+            }
+            tracked
+        });
+        frame
+            .events
+            .extend(lost.into_iter().map(FrameUpdateEvent::AnchorLost));
+        frame.anchors = self
+            .anchors
+            .iter()
+            .map(|anchor| AnchorPose {
+                id: anchor.id,
+                pose: anchor.pose,
+            })
+            .collect();
+
+        if self.granted_features.iter().any(|f| f == "plane-detection") {
+            let events = world::detection_events(&mut self.reported_planes, data.planes.planes());
+            frame.events.extend(events);
+        }
+        if self.granted_features.iter().any(|f| f == "mesh-detection") {
+            let events = world::detection_events(&mut self.reported_meshes, data.meshes.meshes());
+            frame.events.extend(events);
+        }
+
         if data.needs_floor_update {
             frame.events.push(FrameUpdateEvent::UpdateFloorTransform(
                 data.floor_transform.clone(),
             ));
             data.needs_floor_update = false;
         }
+        self.frame_start_ns = time::precise_time_ns();
+        self.frame_time_ns = frame.time_ns;
         Some(frame)
     }
 
     fn end_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) {
         let _ = self.layer_manager().unwrap().end_frame(layers);
-        thread::sleep(std::time::Duration::from_millis(20));
+        let mut data = self.data.lock().unwrap();
+        let frame_pacing = data.frame_pacing;
+        match frame_pacing {
+            MockFramePacing::FixedRate(fps) => {
+                drop(data);
+                let period = (1_000_000_000.0 / fps) as u64;
+                let elapsed = time::precise_time_ns() - self.frame_start_ns;
+                if elapsed < period {
+                    thread::sleep(Duration::from_nanos(period - elapsed));
+                }
+            }
+            MockFramePacing::AsFastAsPossible => {}
+            MockFramePacing::Manual => {
+                // Block until the clock has moved past this frame
+                while data.frame_pacing == MockFramePacing::Manual
+                    && !data.disconnected
+                    && !data.is_ending(self.id)
+                    && data.clock_ns() <= self.frame_time_ns
+                {
+                    data = self.clock.wait(data).unwrap();
+                }
+            }
+        }
     }
 
     fn initial_inputs(&self) -> Vec<InputSource> {
@@ -326,7 +566,12 @@ impl DeviceAPI for HeadlessDevice {
     }
 
     fn quit(&mut self) {
-        self.with_per_session(|s| s.events.callback(Event::SessionEnd))
+        self.with_per_session(|s| {
+            s.ending = true;
+            s.events.callback(Event::SessionEnd)
+        });
+        // Wake up any frames waiting for the clock, so the session can end
+        self.clock.notify_all();
     }
 
     fn set_quitter(&mut self, quitter: Quitter) {
@@ -341,13 +586,91 @@ impl DeviceAPI for HeadlessDevice {
         &self.granted_features
     }
 
+    fn set_visibility(&mut self, visibility: Visibility) {
+        self.data
+            .lock()
+            .unwrap()
+            .set_visibility(self.id, visibility)
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        // Hit tests can find any region of the mock world
+        let mut capabilities = DeviceCapabilities::new(self.granted_features.clone(), true);
+        capabilities.entity_types = EntityTypes {
+            point: true,
+            plane: true,
+            mesh: true,
+        };
+        // Nothing is displayed, so any layer the layer manager can allocate is fine
+        capabilities.layer_types.extend(&[
+            LayerType::Quad,
+            LayerType::Cylinder,
+            LayerType::Equirect,
+            LayerType::Cube,
+        ]);
+        capabilities.texture_arrays = true;
+        capabilities
+    }
+
     fn request_hit_test(&mut self, source: HitTestSource) {
         self.hit_tests.request_hit_test(source)
     }
 
+    fn request_hit_test_for_transient_input(&mut self, source: TransientHitTestSource) {
+        self.hit_tests.request_hit_test_for_transient_input(source)
+    }
+
     fn cancel_hit_test(&mut self, id: HitTestId) {
         self.hit_tests.cancel_hit_test(id)
     }
+
+    fn create_anchor(&mut self, source: AnchorSource) -> Result<AnchorId, Error> {
+        if !self.granted_features.iter().any(|f| f == "anchors") {
+            return Err(Error::FeatureNotGranted("anchors".into()));
+        }
+        let data = self.data.lock().unwrap();
+        let (pose, attached) = match source {
+            AnchorSource::Space(space, pose) => {
+                let origin = data
+                    .native_origin(space)
+                    .ok_or_else(|| Error::InvalidState("Space is not tracked".into()))?;
+                (pose.post_transform(&origin), false)
+            }
+            AnchorSource::HitTest(result) => (result.space.cast_unit(), data.world.is_some()),
+        };
+        drop(data);
+        let id = AnchorId(self.next_anchor_id);
+        self.next_anchor_id += 1;
+        self.anchors.push(Anchor { id, pose, attached });
+        Ok(id)
+    }
+
+    fn delete_anchor(&mut self, id: AnchorId) {
+        self.anchors.retain(|anchor| anchor.id != id)
+    }
+}
+
+/// Whether a pose lies on one of the faces of the world
+fn on_surface(world: &MockWorld, pose: RigidTransform3D<f32, AnchorSpace, Native>) -> bool {
+    const TOLERANCE: f32 = 0.001;
+    let point = pose.translation;
+    world
+        .regions
+        .iter()
+        .flat_map(|region| &region.faces)
+        .any(|triangle| {
+            // Cast a short ray through the face at the point
+            let normal = (triangle.second - triangle.first)
+                .cross(triangle.third - triangle.first)
+                .normalize();
+            let ray = Ray {
+                origin: point + normal * TOLERANCE,
+                direction: -normal,
+            };
+            triangle.intersect(ray).map_or(false, |hit| {
+                (hit.translation - point).length() < 2. * TOLERANCE
+            })
+        })
 }
 
 impl HeadlessMockDiscovery {
@@ -364,9 +687,109 @@ macro_rules! with_all_sessions {
     };
 }
 
+/// Inputs are only reported to the sessions which have focus
+macro_rules! with_visible_sessions {
+    ($self:ident, |$s:ident| $e:expr) => {
+        for $s in &mut $self.sessions {
+            if $s.visibility == Visibility::Visible {
+                $e;
+            }
+        }
+    };
+}
+
 impl HeadlessDeviceData {
+    /// Recenter native space on the viewer, and tell the sessions about it
+    fn reset_pose(&mut self) {
+        let viewer = match self.viewer_origin {
+            Some(viewer) => viewer,
+            None => return,
+        };
+        let forward = viewer
+            .rotation
+            .transform_vector3d(Vector3D::new(0., 0., -1.));
+        let yaw = Angle::radians((-forward.x).atan2(-forward.z));
+        let translation = Vector3D::new(viewer.translation.x, 0., viewer.translation.z);
+        // The pose of the new native origin in the old native space
+        let reset: RigidTransform3D<f32, Native, Native> =
+            RigidTransform3D::new(Rotation3D::around_y(yaw), translation);
+
+        // Everything we know about is fixed in the real world,
+        // so moves in the opposite direction in native space
+        let rebase = reset.inverse();
+        self.viewer_origin = Some(viewer.post_transform(&rebase));
+        for input in &mut self.inputs {
+            input.pointer = input.pointer.map(|pointer| pointer.post_transform(&rebase));
+            input.grip = input.grip.map(|grip| grip.post_transform(&rebase));
+            if let Some(ref mut hand) = input.hand {
+                **hand = hand.map(|joint, _| {
+                    joint.map(|joint| JointFrame {
+                        pose: joint.pose.post_transform(&rebase),
+                        ..joint
+                    })
+                });
+            }
+        }
+        if let Some(ref mut world) = self.world {
+            let rebase_point = |point: Point3D<f32, Native>| {
+                rebase.rotation.transform_point3d(point) + rebase.translation
+            };
+            for face in world.regions.iter_mut().flat_map(|r| r.faces.iter_mut()) {
+                face.first = rebase_point(face.first);
+                face.second = rebase_point(face.second);
+                face.third = rebase_point(face.third);
+            }
+            for plane in &mut world.planes {
+                plane.pose = plane.pose.post_transform(&rebase);
+            }
+        }
+        self.update_detections();
+
+        // The floor moves along with native space, so the floor transform is unchanged
+        let mut resets = vec![(BaseSpace::Local, reset.cast_unit())];
+        if let Some(floor) = self.floor_transform {
+            let floor_reset = floor
+                .inverse()
+                .post_transform(&reset)
+                .post_transform(&floor);
+            resets.push((BaseSpace::Floor, floor_reset.cast_unit()));
+            resets.push((BaseSpace::BoundedFloor, floor_reset.cast_unit()));
+        }
+        for s in &mut self.sessions {
+            s.native_reset = Some(match s.native_reset {
+                Some(earlier) => earlier.post_transform(&rebase),
+                None => rebase,
+            });
+            for &(base, transform) in &resets {
+                s.events
+                    .callback(Event::ReferenceSpaceReset(base, Some(transform)));
+            }
+        }
+    }
+
+    /// Update what is detected in the world, after the world changes
+    fn update_detections(&mut self) {
+        let time_ns = self.clock_ns();
+        self.planes.update(self.world.as_ref(), time_ns);
+        self.meshes.update(self.world.as_ref(), time_ns);
+    }
+
+    /// The current time of the device clock. This only follows
+    /// the real clock when frames are not being paced manually.
+    fn clock_ns(&self) -> u64 {
+        match self.frame_pacing {
+            MockFramePacing::Manual => self.clock_ns,
+            _ => self.clock_ns + (time::precise_time_ns() - self.clock_synced_ns),
+        }
+    }
+
+    fn sync_clock(&mut self) {
+        self.clock_ns = self.clock_ns();
+        self.clock_synced_ns = time::precise_time_ns();
+    }
+
     fn get_frame(&self, s: &PerSessionData, sub_images: Vec<SubImages>) -> Frame {
-        let time_ns = time::precise_time_ns();
+        let time_ns = self.clock_ns();
         let views = self.views.clone();
 
         let pose = self.viewer_origin.map(|transform| {
@@ -392,9 +815,10 @@ impl HeadlessDeviceData {
                 id: i.source.id,
                 target_ray_origin: i.pointer,
                 grip_origin: i.grip,
-                pressed: false,
-                squeezed: false,
-                hand: None,
+                pressed: i.gestures.selecting(),
+                squeezed: i.gestures.squeezing(),
+                hand: i.hand.clone(),
+                gamepad: i.gamepad.clone(),
             })
             .collect();
         Frame {
@@ -405,6 +829,8 @@ impl HeadlessDeviceData {
             sub_images,
             sent_time: 0,
             hit_test_results: vec![],
+            transient_hit_test_results: vec![],
+            anchors: vec![],
         }
     }
 
@@ -420,8 +846,30 @@ impl HeadlessDeviceData {
         Viewports { viewports: vec }
     }
 
+    /// A session gains all the active inputs when it gains focus, and loses them when it loses focus
+    fn set_visibility(&mut self, id: u32, visibility: Visibility) {
+        let inputs = self.inputs.iter().filter(|i| i.active);
+        let s = self.sessions.iter_mut().find(|s| s.id == id).unwrap();
+        let was_visible = s.visibility == Visibility::Visible;
+        let is_visible = visibility == Visibility::Visible;
+        s.visibility = visibility;
+        if is_visible && !was_visible {
+            for input in inputs {
+                s.events.callback(Event::AddInput(input.source.clone()));
+            }
+        } else if was_visible && !is_visible {
+            for input in inputs {
+                s.events.callback(Event::RemoveInput(input.source.id));
+            }
+        }
+    }
+
+    /// Send a select event to the sessions which have focus
     fn trigger_select(&mut self, id: InputId, kind: SelectKind, event: SelectEvent) {
         for i in 0..self.sessions.len() {
+            if self.sessions[i].visibility != Visibility::Visible {
+                continue;
+            }
             let frame = self.get_frame(&self.sessions[i], Vec::new());
             self.sessions[i]
                 .events
@@ -429,10 +877,70 @@ impl HeadlessDeviceData {
         }
     }
 
+    /// Recognize the gestures made by hands since the last frame,
+    /// once all of their joints have been updated
+    fn recognize_gestures(&mut self) {
+        for i in 0..self.inputs.len() {
+            let input = &mut self.inputs[i];
+            if !input.active {
+                continue;
+            }
+            let id = input.source.id;
+            let gestures = input.gestures.update(input.hand.as_deref());
+            for (kind, event) in gestures {
+                self.trigger_select(id, kind, event);
+            }
+        }
+    }
+
+    /// Apply any scripted messages that are due at the start of this frame,
+    /// returning false if they disconnected the device
+    fn apply_timeline(&mut self) -> bool {
+        let frame = self.frame_count;
+        self.frame_count += 1;
+        while self
+            .timeline
+            .first()
+            .map_or(false, |&(due, _)| due <= frame)
+        {
+            let (_, msg) = self.timeline.remove(0);
+            if !self.handle_msg(msg) {
+                self.timeline.clear();
+                return false;
+            }
+        }
+        true
+    }
+
     fn handle_msg(&mut self, msg: MockDeviceMsg) -> bool {
         match msg {
-            MockDeviceMsg::SetWorld(w) => self.world = Some(w),
-            MockDeviceMsg::ClearWorld => self.world = None,
+            MockDeviceMsg::SetWorld(w) => {
+                self.world = Some(w);
+                self.update_detections();
+            }
+            MockDeviceMsg::ClearWorld => {
+                self.world = None;
+                self.update_detections();
+            }
+            MockDeviceMsg::SetTimeline(timeline) => {
+                self.timeline.extend(timeline.entries);
+                // A stable sort, so messages for the same frame keep their order
+                self.timeline.sort_by_key(|&(due, _)| due);
+            }
+            MockDeviceMsg::SetFramePacing(frame_pacing) => {
+                if let MockFramePacing::FixedRate(fps) = frame_pacing {
+                    if !fps.is_finite() || fps <= 0. {
+                        log::warn!("Ignoring invalid frame rate {}", fps);
+                        return true;
+                    }
+                }
+                self.sync_clock();
+                self.frame_pacing = frame_pacing;
+            }
+            MockDeviceMsg::AdvanceClock(ns) => {
+                self.sync_clock();
+                self.clock_ns += ns;
+            }
             MockDeviceMsg::SetViewerOrigin(viewer_origin) => {
                 self.viewer_origin = viewer_origin;
             }
@@ -440,6 +948,13 @@ impl HeadlessDeviceData {
                 self.floor_transform = floor_origin.map(|f| f.inverse());
                 self.needs_floor_update = true;
             }
+            MockDeviceMsg::SetBoundsGeometry(bounds) => {
+                self.bounds_geometry = bounds;
+                with_all_sessions!(self, |s| {
+                    s.needs_bounds_update = true;
+                })
+            }
+            MockDeviceMsg::SimulateResetPose => self.reset_pose(),
             MockDeviceMsg::SetViews(views) => {
                 self.views = views;
                 with_all_sessions!(self, |s| {
@@ -447,17 +962,23 @@ impl HeadlessDeviceData {
                 })
             }
             MockDeviceMsg::VisibilityChange(v) => {
-                with_all_sessions!(self, |s| s.events.callback(Event::VisibilityChange(v)))
+                // The registry decides what this means for each session
+                if let Some(ref mut notifier) = self.notifier {
+                    notifier.set_visibility(v);
+                }
             }
             MockDeviceMsg::AddInputSource(init) => {
                 self.inputs.push(InputInfo {
                     source: init.source.clone(),
                     pointer: init.pointer_origin,
                     grip: init.grip_origin,
+                    hand: init.hand,
+                    gestures: Default::default(),
+                    gamepad: init.gamepad,
                     active: true,
                     clicking: false,
                 });
-                with_all_sessions!(self, |s| s
+                with_visible_sessions!(self, |s| s
                     .events
                     .callback(Event::AddInput(init.source.clone())))
             }
@@ -466,27 +987,48 @@ impl HeadlessDeviceData {
                     match msg {
                         MockInputMsg::SetHandedness(h) => {
                             input.source.handedness = h;
-                            with_all_sessions!(self, |s| {
+                            with_visible_sessions!(self, |s| {
                                 s.events
                                     .callback(Event::UpdateInput(id, input.source.clone()))
                             });
                         }
                         MockInputMsg::SetProfiles(p) => {
                             input.source.profiles = p;
-                            with_all_sessions!(self, |s| {
+                            with_visible_sessions!(self, |s| {
                                 s.events
                                     .callback(Event::UpdateInput(id, input.source.clone()))
                             });
                         }
                         MockInputMsg::SetTargetRayMode(t) => {
                             input.source.target_ray_mode = t;
-                            with_all_sessions!(self, |s| {
+                            with_visible_sessions!(self, |s| {
                                 s.events
                                     .callback(Event::UpdateInput(id, input.source.clone()))
                             });
                         }
                         MockInputMsg::SetPointerOrigin(p) => input.pointer = p,
                         MockInputMsg::SetGripOrigin(p) => input.grip = p,
+                        MockInputMsg::SetHandSupport(h) => {
+                            input.source.hand_support = h;
+                            with_visible_sessions!(self, |s| {
+                                s.events
+                                    .callback(Event::UpdateInput(id, input.source.clone()))
+                            });
+                        }
+                        MockInputMsg::SetHand(h) => input.hand = h,
+                        MockInputMsg::SetGamepad(g) => input.gamepad = g,
+                        MockInputMsg::SetGamepadButton(index, button) => input
+                            .gamepad
+                            .get_or_insert_with(Gamepad::xr_standard)
+                            .set_button(index, button),
+                        MockInputMsg::SetGamepadAxis(index, value) => input
+                            .gamepad
+                            .get_or_insert_with(Gamepad::xr_standard)
+                            .set_axis(index, value),
+                        MockInputMsg::SetHandJoint(joint, frame) => {
+                            let hand = input.hand.get_or_insert_with(Default::default);
+                            *hand.get_mut(joint) = frame;
+                        }
                         MockInputMsg::TriggerSelect(kind, event) => {
                             if !input.active {
                                 return true;
@@ -512,16 +1054,17 @@ impl HeadlessDeviceData {
                         }
                         MockInputMsg::Disconnect => {
                             if input.active {
-                                with_all_sessions!(self, |s| s
+                                with_visible_sessions!(self, |s| s
                                     .events
                                     .callback(Event::RemoveInput(input.source.id)));
                                 input.active = false;
                                 input.clicking = false;
+                                input.gestures = Default::default();
                             }
                         }
                         MockInputMsg::Reconnect => {
                             if !input.active {
-                                with_all_sessions!(self, |s| s
+                                with_visible_sessions!(self, |s| s
                                     .events
                                     .callback(Event::AddInput(input.source.clone())));
                                 input.active = true;
@@ -533,6 +1076,9 @@ impl HeadlessDeviceData {
             MockDeviceMsg::Disconnect(s) => {
                 self.disconnected = true;
                 with_all_sessions!(self, |s| s.quitter.as_ref().map(|q| q.quit()));
+                if let Some(ref mut notifier) = self.notifier {
+                    notifier.disconnected();
+                }
                 // notify the client that we're done disconnecting
                 let _ = s.send(());
                 return false;
@@ -541,10 +1087,13 @@ impl HeadlessDeviceData {
         true
     }
 
-    fn native_ray(&self, ray: Ray<ApiSpace>, space: Space) -> Option<Ray<Native>> {
+    fn native_origin(&self, space: Space) -> Option<RigidTransform3D<f32, ApiSpace, Native>> {
         let origin: RigidTransform3D<f32, ApiSpace, Native> = match space.base {
             BaseSpace::Local => RigidTransform3D::identity(),
-            BaseSpace::Floor => self.floor_transform?.inverse().cast_unit(),
+            BaseSpace::Floor | BaseSpace::BoundedFloor => {
+                self.floor_transform?.inverse().cast_unit()
+            }
+            BaseSpace::Unbounded => RigidTransform3D::identity(),
             BaseSpace::Viewer => self.viewer_origin?.cast_unit(),
             BaseSpace::TargetRay(id) => self
                 .inputs
@@ -558,9 +1107,47 @@ impl HeadlessDeviceData {
                 .find(|i| i.source.id == id)?
                 .grip?
                 .cast_unit(),
-            BaseSpace::Joint(..) => panic!("Cannot request mocking backend with hands"),
+            BaseSpace::Joint(id, joint) => self
+                .inputs
+                .iter()
+                .find(|i| i.source.id == id)?
+                .hand
+                .as_ref()?
+                .get(joint)?
+                .pose
+                .cast_unit(),
+        };
+        Some(origin.pre_transform(&space.offset))
+    }
+
+    /// Hit test the world with a ray in the given space
+    fn hit_test(
+        &self,
+        id: HitTestId,
+        ray: Ray<ApiSpace>,
+        space: Space,
+        types: EntityTypes,
+    ) -> Vec<HitTestResult> {
+        let world = match self.world {
+            Some(ref world) => world,
+            None => return vec![],
+        };
+        let ray = match self.native_ray(ray, space) {
+            Some(ray) => ray,
+            None => return vec![],
         };
-        let space_origin = origin.pre_transform(&space.offset);
+        world
+            .regions
+            .iter()
+            .filter(|region| types.is_type(region.ty))
+            .flat_map(|region| &region.faces)
+            .filter_map(|triangle| triangle.intersect(ray))
+            .map(|space| HitTestResult { space, id })
+            .collect()
+    }
+
+    fn native_ray(&self, ray: Ray<ApiSpace>, space: Space) -> Option<Ray<Native>> {
+        let space_origin = self.native_origin(space)?;
 
         let origin_rigid: RigidTransform3D<f32, ApiSpace, ApiSpace> = ray.origin.into();
         Some(Ray {
@@ -0,0 +1,388 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! The real world geometry detected by the headless device, derived from the mock world.
+
+use euclid::approxeq::ApproxEq;
+use euclid::Angle;
+use euclid::Point3D;
+use euclid::RigidTransform3D;
+use euclid::Rotation3D;
+use euclid::Vector3D;
+
+use std::cmp::Ordering;
+
+use webxr_api::EntityType;
+use webxr_api::FrameUpdateEvent;
+use webxr_api::Mesh;
+use webxr_api::MeshId;
+use webxr_api::MeshSpace;
+use webxr_api::MockPlane;
+use webxr_api::MockRegion;
+use webxr_api::MockWorld;
+use webxr_api::Native;
+use webxr_api::Plane;
+use webxr_api::PlaneId;
+use webxr_api::PlaneOrientation;
+use webxr_api::PlaneSpace;
+use webxr_api::Triangle;
+
+/// How far apart points can be while still being considered the same, in meters
+const TOLERANCE: f32 = 0.001;
+
+/// Where a detected plane comes from in the mock world
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PlaneSource {
+    /// A plane given explicitly, with its mock id
+    Plane(u32),
+    /// A plane region, with its mock id
+    Region(u32),
+}
+
+/// The planes detected in the world. Planes are matched up by the ids
+/// of what they come from, so that they keep their ids as the world changes.
+#[derive(Default)]
+pub(crate) struct DetectedPlanes {
+    planes: Vec<Plane>,
+    /// The source of each plane
+    sources: Vec<PlaneSource>,
+    next_id: u32,
+}
+
+impl DetectedPlanes {
+    pub fn update(&mut self, world: Option<&MockWorld>, time_ns: u64) {
+        let mocks = world.map(world_planes).unwrap_or_default();
+        let mut planes = Vec::with_capacity(mocks.len());
+        let mut sources = Vec::with_capacity(mocks.len());
+        for (source, mock) in mocks {
+            if sources.contains(&source) {
+                log::warn!("Ignoring mock plane with duplicate id {:?}", source);
+                continue;
+            }
+            let plane = match self.sources.iter().position(|s| *s == source) {
+                Some(i) if same_plane(&self.planes[i], &mock) => self.planes[i].clone(),
+                existing => {
+                    let id = match existing {
+                        Some(i) => self.planes[i].id,
+                        None => {
+                            let id = PlaneId(self.next_id);
+                            self.next_id += 1;
+                            id
+                        }
+                    };
+                    Plane {
+                        id,
+                        pose: mock.pose,
+                        polygon: mock.polygon,
+                        orientation: mock.orientation,
+                        last_changed_time: time_ns,
+                    }
+                }
+            };
+            planes.push(plane);
+            sources.push(source);
+        }
+        self.planes = planes;
+        self.sources = sources;
+    }
+
+    pub fn planes(&self) -> &[Plane] {
+        &self.planes
+    }
+}
+
+/// The meshes detected in the world, one for each region.
+/// Like planes, meshes are matched up by the ids of their regions.
+#[derive(Default)]
+pub(crate) struct DetectedMeshes {
+    meshes: Vec<Mesh>,
+    /// The mock id of the region of each mesh
+    regions: Vec<u32>,
+    next_id: u32,
+}
+
+impl DetectedMeshes {
+    pub fn update(&mut self, world: Option<&MockWorld>, time_ns: u64) {
+        let regions = world.map_or(&[][..], |world| &world.regions[..]);
+        let mut meshes = Vec::with_capacity(regions.len());
+        let mut ids = Vec::with_capacity(regions.len());
+        for region in regions {
+            if ids.contains(&region.id) {
+                log::warn!("Ignoring mock region with duplicate id {}", region.id);
+                continue;
+            }
+            let (vertices, indices) = region_mesh(&region.faces);
+            let mesh = match self.regions.iter().position(|id| *id == region.id) {
+                Some(i) if same_mesh(&self.meshes[i], &vertices, &indices, region) => {
+                    self.meshes[i].clone()
+                }
+                existing => {
+                    let id = match existing {
+                        Some(i) => self.meshes[i].id,
+                        None => {
+                            let id = MeshId(self.next_id);
+                            self.next_id += 1;
+                            id
+                        }
+                    };
+                    Mesh {
+                        id,
+                        // The mock world is given in native space
+                        pose: RigidTransform3D::identity(),
+                        vertices,
+                        indices,
+                        semantic_label: region.semantic_label.clone(),
+                        last_changed_time: time_ns,
+                    }
+                }
+            };
+            meshes.push(mesh);
+            ids.push(region.id);
+        }
+        self.meshes = meshes;
+        self.regions = ids;
+    }
+
+    pub fn meshes(&self) -> &[Mesh] {
+        &self.meshes
+    }
+}
+
+/// Something detected in the world, which sessions are told about as it changes
+pub(crate) trait Detected: Clone {
+    type Id: Copy + PartialEq;
+    fn id(&self) -> Self::Id;
+    fn last_changed_time(&self) -> u64;
+    fn added(self) -> FrameUpdateEvent;
+    fn changed(self) -> FrameUpdateEvent;
+    fn removed(id: Self::Id) -> FrameUpdateEvent;
+}
+
+impl Detected for Plane {
+    type Id = PlaneId;
+
+    fn id(&self) -> PlaneId {
+        self.id
+    }
+
+    fn last_changed_time(&self) -> u64 {
+        self.last_changed_time
+    }
+
+    fn added(self) -> FrameUpdateEvent {
+        FrameUpdateEvent::PlaneAdded(self)
+    }
+
+    fn changed(self) -> FrameUpdateEvent {
+        FrameUpdateEvent::PlaneChanged(self)
+    }
+
+    fn removed(id: PlaneId) -> FrameUpdateEvent {
+        FrameUpdateEvent::PlaneRemoved(id)
+    }
+}
+
+impl Detected for Mesh {
+    type Id = MeshId;
+
+    fn id(&self) -> MeshId {
+        self.id
+    }
+
+    fn last_changed_time(&self) -> u64 {
+        self.last_changed_time
+    }
+
+    fn added(self) -> FrameUpdateEvent {
+        FrameUpdateEvent::MeshAdded(self)
+    }
+
+    fn changed(self) -> FrameUpdateEvent {
+        FrameUpdateEvent::MeshChanged(self)
+    }
+
+    fn removed(id: MeshId) -> FrameUpdateEvent {
+        FrameUpdateEvent::MeshRemoved(id)
+    }
+}
+
+/// The events needed to bring a session's view of what has been detected up to date
+pub(crate) fn detection_events<T: Detected>(
+    reported: &mut Vec<T>,
+    detected: &[T],
+) -> Vec<FrameUpdateEvent> {
+    let mut events = vec![];
+    for old in reported.iter() {
+        if !detected.iter().any(|new| new.id() == old.id()) {
+            events.push(T::removed(old.id()));
+        }
+    }
+    for new in detected {
+        match reported.iter().find(|old| old.id() == new.id()) {
+            Some(old) if old.last_changed_time() == new.last_changed_time() => (),
+            Some(_) => events.push(new.clone().changed()),
+            None => events.push(new.clone().added()),
+        }
+    }
+    *reported = detected.to_vec();
+    events
+}
+
+fn same_mesh(
+    mesh: &Mesh,
+    vertices: &[Point3D<f32, MeshSpace>],
+    indices: &[u32],
+    region: &MockRegion,
+) -> bool {
+    mesh.vertices == vertices
+        && mesh.indices == indices
+        && mesh.semantic_label == region.semantic_label
+}
+
+fn same_plane(plane: &Plane, mock: &MockPlane) -> bool {
+    plane.orientation == mock.orientation
+        && plane.pose.rotation.approx_eq(&mock.pose.rotation)
+        && plane.pose.translation.approx_eq(&mock.pose.translation)
+        && plane.polygon.len() == mock.polygon.len()
+        && plane
+            .polygon
+            .iter()
+            .zip(&mock.polygon)
+            .all(|(a, b)| a.approx_eq(b))
+}
+
+/// The planes in the world, both those declared explicitly,
+/// and those made from plane regions whose faces are coplanar
+fn world_planes(world: &MockWorld) -> Vec<(PlaneSource, MockPlane)> {
+    let explicit = world
+        .planes
+        .iter()
+        .map(|plane| (PlaneSource::Plane(plane.id), plane.clone()));
+    let derived = world
+        .regions
+        .iter()
+        .filter(|region| matches!(region.ty, EntityType::Plane))
+        .filter_map(|region| {
+            let plane = region_plane(region.id, &region.faces)?;
+            Some((PlaneSource::Region(region.id), plane))
+        });
+    explicit.chain(derived).collect()
+}
+
+/// The plane containing all the faces, if they are coplanar
+fn region_plane(id: u32, faces: &[Triangle]) -> Option<MockPlane> {
+    let normal = faces.iter().map(face_normal).find(|n| n.length() > 0.)?;
+    let normal = normal.normalize();
+    let points: Vec<Point3D<f32, Native>> = faces
+        .iter()
+        .flat_map(|face| vec![face.first, face.second, face.third])
+        .collect();
+    let origin = points[0];
+    let coplanar = points
+        .iter()
+        .all(|point| (*point - origin).dot(normal).abs() < TOLERANCE);
+    if !coplanar {
+        return None;
+    }
+
+    let sum = points
+        .iter()
+        .fold(Vector3D::zero(), |sum, point| sum + point.to_vector());
+    let centroid = sum / points.len() as f32;
+    let rotation = rotation_to(normal);
+    let pose = RigidTransform3D::new(rotation, centroid);
+
+    // The outline of the plane is the convex hull of the points
+    let inverse = rotation.inverse();
+    let projected: Vec<(f32, f32)> = points
+        .iter()
+        .map(|point| inverse.transform_vector3d(point.to_vector() - centroid))
+        .map(|v| (v.x, v.z))
+        .collect();
+    let polygon = convex_hull(projected)
+        .into_iter()
+        .map(|(x, z)| Point3D::new(x, 0., z))
+        .collect();
+
+    let orientation = if normal.y.abs() > 1. - TOLERANCE {
+        Some(PlaneOrientation::Horizontal)
+    } else if normal.y.abs() < TOLERANCE {
+        Some(PlaneOrientation::Vertical)
+    } else {
+        None
+    };
+    Some(MockPlane {
+        id,
+        pose,
+        polygon,
+        orientation,
+    })
+}
+
+/// The vertices and indices of the mesh made of the faces, sharing vertices between faces
+fn region_mesh(faces: &[Triangle]) -> (Vec<Point3D<f32, MeshSpace>>, Vec<u32>) {
+    let mut vertices: Vec<Point3D<f32, MeshSpace>> = vec![];
+    let mut indices = vec![];
+    for face in faces {
+        for point in &[face.first, face.second, face.third] {
+            let point = point.cast_unit();
+            let index = match vertices.iter().position(|vertex| *vertex == point) {
+                Some(index) => index,
+                None => {
+                    vertices.push(point);
+                    vertices.len() - 1
+                }
+            };
+            indices.push(index as u32);
+        }
+    }
+    (vertices, indices)
+}
+
+fn face_normal(face: &Triangle) -> Vector3D<f32, Native> {
+    (face.second - face.first).cross(face.third - face.first)
+}
+
+/// The rotation taking the Y axis to the given normal
+fn rotation_to(normal: Vector3D<f32, Native>) -> Rotation3D<f32, PlaneSpace, Native> {
+    let y = Vector3D::new(0., 1., 0.);
+    let axis = y.cross(normal);
+    let sin = axis.length();
+    let cos = y.dot(normal);
+    if sin > TOLERANCE {
+        Rotation3D::around_axis(axis.cast_unit(), Angle::radians(sin.atan2(cos)))
+    } else if cos > 0. {
+        Rotation3D::identity()
+    } else {
+        Rotation3D::around_x(Angle::pi())
+    }
+}
+
+/// https://en.wikibooks.org/wiki/Algorithm_Implementation/Geometry/Convex_hull/Monotone_chain
+fn convex_hull(mut points: Vec<(f32, f32)>) -> Vec<(f32, f32)> {
+    points.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    points.dedup_by(|a, b| (a.0 - b.0).abs() < TOLERANCE && (a.1 - b.1).abs() < TOLERANCE);
+    if points.len() < 3 {
+        return points;
+    }
+    let mut hull = half_hull(points.iter());
+    hull.extend(half_hull(points.iter().rev()));
+    hull
+}
+
+/// One half of a convex hull, without its last point, which starts the other half
+fn half_hull<'a>(points: impl Iterator<Item = &'a (f32, f32)>) -> Vec<(f32, f32)> {
+    let cross = |o: (f32, f32), a: (f32, f32), b: (f32, f32)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+    let mut hull: Vec<(f32, f32)> = Vec::new();
+    for &point in points {
+        while hull.len() >= 2 && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0. {
+            hull.pop();
+        }
+        hull.push(point);
+    }
+    hull.pop();
+    hull
+}
@@ -28,6 +28,9 @@ mod egl;
 #[cfg(feature = "openxr-api")]
 pub mod openxr;
 
+#[cfg(feature = "recording")]
+pub mod recording;
+
 pub mod surfman_layer_manager;
 pub use surfman_layer_manager::SurfmanGL;
 pub use surfman_layer_manager::SurfmanLayerManager;
@@ -2,9 +2,11 @@ use euclid::RigidTransform3D;
 use openxr::d3d::D3D11;
 use openxr::{
     self, Action, ActionSet, Binding, FrameState, Hand as HandEnum, HandJoint, HandTracker,
-    Instance, Path, Posef, Session, Space, SpaceLocationFlags,
+    Instance, Path, Posef, Session, Space, SpaceLocationFlags, Vector2f,
 };
 use webxr_api::Finger;
+use webxr_api::Gamepad;
+use webxr_api::GamepadButton;
 use webxr_api::Hand;
 use webxr_api::Handedness;
 use webxr_api::Input;
@@ -82,6 +84,12 @@ pub struct OpenXRInput {
     action_grip_space: Space,
     action_click: Action<bool>,
     action_squeeze: Action<bool>,
+    action_trigger: Action<f32>,
+    action_thumbstick: Action<Vector2f>,
+    action_thumbstick_click: Action<bool>,
+    action_trackpad: Action<Vector2f>,
+    action_trackpad_click: Action<bool>,
+    action_trackpad_touch: Action<bool>,
     handedness: Handedness,
     click_state: ClickState,
     squeeze_state: ClickState,
@@ -141,6 +149,48 @@ impl OpenXRInput {
                 &[],
             )
             .unwrap();
+        let action_trigger: Action<f32> = action_set
+            .create_action(
+                &format!("{}_hand_trigger", hand),
+                &format!("{} hand trigger", hand),
+                &[],
+            )
+            .unwrap();
+        let action_thumbstick: Action<Vector2f> = action_set
+            .create_action(
+                &format!("{}_hand_thumbstick", hand),
+                &format!("{} hand thumbstick", hand),
+                &[],
+            )
+            .unwrap();
+        let action_thumbstick_click: Action<bool> = action_set
+            .create_action(
+                &format!("{}_hand_thumbstick_click", hand),
+                &format!("{} hand thumbstick click", hand),
+                &[],
+            )
+            .unwrap();
+        let action_trackpad: Action<Vector2f> = action_set
+            .create_action(
+                &format!("{}_hand_trackpad", hand),
+                &format!("{} hand trackpad", hand),
+                &[],
+            )
+            .unwrap();
+        let action_trackpad_click: Action<bool> = action_set
+            .create_action(
+                &format!("{}_hand_trackpad_click", hand),
+                &format!("{} hand trackpad click", hand),
+                &[],
+            )
+            .unwrap();
+        let action_trackpad_touch: Action<bool> = action_set
+            .create_action(
+                &format!("{}_hand_trackpad_touch", hand),
+                &format!("{} hand trackpad touch", hand),
+                &[],
+            )
+            .unwrap();
 
         let hand_tracker = if needs_hands {
             let hand = match handedness {
@@ -161,6 +211,12 @@ impl OpenXRInput {
             action_grip_space,
             action_click,
             action_squeeze,
+            action_trigger,
+            action_thumbstick,
+            action_thumbstick_click,
+            action_trackpad,
+            action_trackpad_click,
+            action_trackpad_touch,
             handedness,
             click_state: ClickState::Done,
             squeeze_state: ClickState::Done,
@@ -197,6 +253,8 @@ impl OpenXRInput {
                 .get_bindings(instance, "trigger/value", Some("squeeze/click"))
                 .into_iter(),
         );
+        bindings.extend(right_hand.get_gamepad_bindings(instance).into_iter());
+        bindings.extend(left_hand.get_gamepad_bindings(instance).into_iter());
         let path_controller = instance
             .string_to_path("/interaction_profiles/microsoft/motion_controller")
             .unwrap();
@@ -252,6 +310,83 @@ impl OpenXRInput {
         ret
     }
 
+    /// The bindings for the xr-standard gamepad of a motion controller
+    fn get_gamepad_bindings(&self, instance: &Instance) -> Vec<Binding> {
+        let hand = hand_str(self.handedness);
+        let path = |input: &str| {
+            instance
+                .string_to_path(&format!("/user/hand/{}/input/{}", hand, input))
+                .unwrap()
+        };
+        vec![
+            Binding::new(&self.action_trigger, path("trigger/value")),
+            Binding::new(&self.action_thumbstick, path("thumbstick")),
+            Binding::new(&self.action_thumbstick_click, path("thumbstick/click")),
+            Binding::new(&self.action_trackpad, path("trackpad")),
+            Binding::new(&self.action_trackpad_click, path("trackpad/click")),
+            Binding::new(&self.action_trackpad_touch, path("trackpad/touch")),
+        ]
+    }
+
+    /// The state of the xr-standard gamepad, if the controller has one
+    fn gamepad(&self, session: &Session<D3D11>, pressed: bool, squeezed: bool) -> Option<Gamepad> {
+        let trigger = self.action_trigger.state(session, Path::NULL).ok()?;
+        if !trigger.is_active {
+            return None;
+        }
+        let bool_state = |action: &Action<bool>| {
+            action
+                .state(session, Path::NULL)
+                .map(|state| state.is_active && state.current_state)
+                .unwrap_or(false)
+        };
+        let vector_state = |action: &Action<Vector2f>| {
+            action
+                .state(session, Path::NULL)
+                .ok()
+                .filter(|state| state.is_active)
+                .map(|state| state.current_state)
+                .unwrap_or(Vector2f { x: 0., y: 0. })
+        };
+        let button = |pressed: bool, touched: bool| GamepadButton {
+            pressed,
+            touched: pressed || touched,
+            value: if pressed { 1.0 } else { 0.0 },
+        };
+        let thumbstick = vector_state(&self.action_thumbstick);
+        let trackpad = vector_state(&self.action_trackpad);
+        let mut gamepad = Gamepad::xr_standard();
+        gamepad.set_button(
+            Gamepad::TRIGGER,
+            GamepadButton {
+                pressed,
+                touched: pressed || trigger.current_state > 0.0,
+                value: trigger.current_state,
+            },
+        );
+        gamepad.set_button(Gamepad::SQUEEZE, button(squeezed, false));
+        gamepad.set_button(
+            Gamepad::TOUCHPAD,
+            button(
+                bool_state(&self.action_trackpad_click),
+                bool_state(&self.action_trackpad_touch),
+            ),
+        );
+        gamepad.set_button(
+            Gamepad::THUMBSTICK,
+            button(
+                bool_state(&self.action_thumbstick_click),
+                thumbstick.x != 0.0 || thumbstick.y != 0.0,
+            ),
+        );
+        // OpenXR has up as positive, the Gamepad API has up as negative
+        gamepad.set_axis(Gamepad::TOUCHPAD_X, trackpad.x);
+        gamepad.set_axis(Gamepad::TOUCHPAD_Y, -trackpad.y);
+        gamepad.set_axis(Gamepad::THUMBSTICK_X, thumbstick.x);
+        gamepad.set_axis(Gamepad::THUMBSTICK_Y, -thumbstick.y);
+        Some(gamepad)
+    }
+
     pub fn frame(
         &mut self,
         session: &Session<D3D11>,
@@ -320,13 +455,18 @@ impl OpenXRInput {
             .and_then(|_origin| self.hand_tracker.as_ref())
             .and_then(|tracker| locate_hand(base_space, tracker, frame_state));
 
+        let pressed = click_is_active && click.current_state;
+        let squeezed = squeeze_is_active && squeeze.current_state;
+        let gamepad = self.gamepad(session, pressed, squeezed);
+
         let input_frame = InputFrame {
             target_ray_origin,
             id: self.id,
-            pressed: click_is_active && click.current_state,
-            squeezed: squeeze_is_active && squeeze.current_state,
+            pressed,
+            squeezed,
             grip_origin,
             hand,
+            gamepad,
         };
 
         Frame {
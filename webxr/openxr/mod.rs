@@ -1,6 +1,7 @@
 use crate::SurfmanGL;
 
 use euclid::Point2D;
+use euclid::Point3D;
 use euclid::Rect;
 use euclid::RigidTransform3D;
 use euclid::Rotation3D;
@@ -14,8 +15,8 @@ use openxr::{
     self, ActionSet, ActiveActionSet, ApplicationInfo, CompositionLayerFlags,
     CompositionLayerProjection, Entry, EnvironmentBlendMode, ExtensionSet, Extent2Di, FormFactor,
     Fovf, FrameState, FrameStream, FrameWaiter, Instance, Posef, Quaternionf, ReferenceSpaceType,
-    SecondaryEndInfo, Session, Space, Swapchain, SwapchainCreateFlags, SwapchainCreateInfo,
-    SwapchainUsageFlags, SystemId, Vector3f, ViewConfigurationType,
+    SecondaryEndInfo, Session, Space, SpaceLocationFlags, Swapchain, SwapchainCreateFlags,
+    SwapchainCreateInfo, SwapchainUsageFlags, SystemId, Vector3f, ViewConfigurationType,
 };
 use sparkle::gl;
 use sparkle::gl::GLuint;
@@ -33,9 +34,11 @@ use surfman::Error as SurfmanError;
 use surfman::SurfaceTexture;
 use webxr_api;
 use webxr_api::util::{self, ClipPlanes};
+use webxr_api::BaseSpace;
 use webxr_api::Capture;
 use webxr_api::ContextId;
 use webxr_api::DeviceAPI;
+use webxr_api::DeviceDescriptor;
 use webxr_api::DiscoveryAPI;
 use webxr_api::Display;
 use webxr_api::Error;
@@ -43,12 +46,14 @@ use webxr_api::Event;
 use webxr_api::EventBuffer;
 use webxr_api::Floor;
 use webxr_api::Frame;
+use webxr_api::FrameUpdateEvent;
 use webxr_api::GLContexts;
 use webxr_api::InputId;
 use webxr_api::InputSource;
 use webxr_api::LayerGrandManager;
 use webxr_api::LayerId;
 use webxr_api::LayerInit;
+use webxr_api::LayerLayout;
 use webxr_api::LayerManager;
 use webxr_api::LayerManagerAPI;
 use webxr_api::LeftEye;
@@ -80,6 +85,9 @@ use input::OpenXRInput;
 
 const HEIGHT: f32 = 1.4;
 
+/// The OpenXR stage space, whose origin is the center of the stage bounds
+enum Stage {}
+
 const IDENTITY_POSE: Posef = Posef {
     orientation: Quaternionf {
         x: 0.,
@@ -316,20 +324,11 @@ impl DiscoveryAPI<SurfmanGL> for OpenXrDiscovery {
             let needs_hands = init.feature_requested("hand-tracking");
             let instance = create_instance(needs_hands).map_err(|e| Error::BackendSpecific(e))?;
 
-            let mut supported_features = vec!["local-floor".into()];
-            if instance.supports_hands {
-                supported_features.push("hand-tracking".into());
-            }
-
-            let granted_features = init.validate(mode, &supported_features)?;
+            // The supported features depend on the session, so they are validated once it exists
+            let init = init.clone();
             let context_menu_provider = self.context_menu_provider.clone_object();
             xr.spawn(move |grand_manager| {
-                OpenXrDevice::new(
-                    instance,
-                    granted_features,
-                    context_menu_provider,
-                    grand_manager,
-                )
+                OpenXrDevice::new(instance, init, mode, context_menu_provider, grand_manager)
             })
         } else {
             Err(Error::NoMatchingDevice)
@@ -339,6 +338,21 @@ impl DiscoveryAPI<SurfmanGL> for OpenXrDiscovery {
     fn supports_session(&self, mode: SessionMode) -> bool {
         mode == SessionMode::ImmersiveAR || mode == SessionMode::ImmersiveVR
     }
+
+    fn descriptor(&self) -> DeviceDescriptor {
+        // The runtime isn't loaded until a session is requested, so we don't know its name yet,
+        // or whether it supports hand tracking, or knows the bounds of the stage
+        DeviceDescriptor {
+            name: "OpenXR runtime".into(),
+            vendor: String::new(),
+            modes: vec![SessionMode::ImmersiveVR, SessionMode::ImmersiveAR],
+            features: vec![
+                "local-floor".into(),
+                "bounded-floor".into(),
+                "hand-tracking".into(),
+            ],
+        }
+    }
 }
 
 struct OpenXrDevice {
@@ -348,6 +362,9 @@ struct OpenXrDevice {
     frame_waiter: FrameWaiter,
     layer_manager: LayerManager,
     viewer_space: Space,
+    /// The stage space, if bounded floor spaces have been granted
+    stage_space: Option<Space>,
+    needs_bounds_update: bool,
     shared_data: Arc<Mutex<Option<SharedData>>>,
     clip_planes: ClipPlanes,
     supports_secondary: bool,
@@ -491,6 +508,10 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
         context_id: ContextId,
         init: LayerInit,
     ) -> Result<LayerId, Error> {
+        // TODO: Use array swap chains for texture array layers?
+        if init.layout() == LayerLayout::TextureArray {
+            return Err(Error::UnsupportedFeature("texture array layers".into()));
+        }
         let guard = self.shared_data.lock().unwrap();
         let data = guard.as_ref().unwrap();
 
@@ -514,18 +535,13 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
         let swapchain = self
             .session
             .create_swapchain(&swapchain_create_info)
-            .map_err(|e| Error::BackendSpecific(format!("Session::create_swapchain {:?}", e)))?;
+            .map_err(|e| Error::SwapChainFailure(format!("Session::create_swapchain {:?}", e)))?;
 
-        // TODO: Treat depth and stencil separately?
         // TODO: Use the openxr API for depth/stencil swap chains?
-        let has_depth_stencil = match init {
-            LayerInit::WebGLLayer { stencil, depth, .. } => stencil | depth,
-            LayerInit::ProjectionLayer { stencil, depth, .. } => stencil | depth,
-        };
-        let depth_stencil_texture = if has_depth_stencil {
+        let depth_stencil_texture = if init.has_depth_stencil() {
             let gl = contexts
                 .bindings(device, context_id)
-                .ok_or(Error::NoMatchingDevice)?;
+                .ok_or(Error::ContextNotFound(context_id))?;
             let depth_stencil_texture = gl.gen_textures(1)[0];
             gl.bind_texture(gl::TEXTURE_2D, depth_stencil_texture);
             gl.tex_image_2d(
@@ -596,7 +612,7 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
         for (_, openxr_layer) in &mut self.openxr_layers {
             if openxr_layer.waited {
                 openxr_layer.swapchain.release_image().map_err(|e| {
-                    Error::BackendSpecific(format!("Session::release_image {:?}", e))
+                    Error::SwapChainFailure(format!("Session::release_image {:?}", e))
                 })?;
                 openxr_layer.waited = false;
             }
@@ -734,19 +750,19 @@ impl LayerManagerAPI<SurfmanGL> for OpenXrLayerManager {
             .map(|&(context_id, layer_id)| {
                 let context = contexts
                     .context(device, context_id)
-                    .ok_or(Error::NoMatchingDevice)?;
+                    .ok_or(Error::ContextNotFound(context_id))?;
                 let openxr_layer = openxr_layers
                     .get_mut(&layer_id)
-                    .ok_or(Error::NoMatchingDevice)?;
+                    .ok_or(Error::LayerNotFound(layer_id))?;
 
                 let image = openxr_layer.swapchain.acquire_image().map_err(|e| {
-                    Error::BackendSpecific(format!("Swapchain::acquire_image {:?}", e))
+                    Error::SwapChainFailure(format!("Swapchain::acquire_image {:?}", e))
                 })?;
                 openxr_layer
                     .swapchain
                     .wait_image(openxr::Duration::INFINITE)
                     .map_err(|e| {
-                        Error::BackendSpecific(format!("Swapchain::wait_image {:?}", e))
+                        Error::SwapChainFailure(format!("Swapchain::wait_image {:?}", e))
                     })?;
                 openxr_layer.waited = true;
 
@@ -803,7 +819,8 @@ fn image_rect(viewport: Rect<i32, Viewport>) -> openxr::Rect2Di {
 impl OpenXrDevice {
     fn new(
         instance: CreatedInstance,
-        granted_features: Vec<String>,
+        init: SessionInit,
+        mode: SessionMode,
         context_menu_provider: Box<dyn ContextMenuProvider>,
         grand_manager: LayerGrandManager<SurfmanGL>,
     ) -> Result<OpenXrDevice, Error> {
@@ -879,6 +896,31 @@ impl OpenXrDevice {
                 Error::BackendSpecific(format!("Session::create_reference_space {:?}", e))
             })?;
 
+        // Bounded floor spaces are only supported if the runtime knows the stage bounds
+        let has_stage_bounds = matches!(
+            session.reference_space_bounds_rect(ReferenceSpaceType::STAGE),
+            Ok(Some(_))
+        );
+        let mut supported_features = vec!["local-floor".into()];
+        if has_stage_bounds {
+            supported_features.push("bounded-floor".into());
+        }
+        if supports_hands {
+            supported_features.push("hand-tracking".into());
+        }
+        let granted_features = init.validate(mode, &supported_features)?;
+
+        let stage_space = if granted_features.iter().any(|f| f == "bounded-floor") {
+            let stage_space = session
+                .create_reference_space(ReferenceSpaceType::STAGE, pose)
+                .map_err(|e| {
+                    Error::BackendSpecific(format!("Session::create_reference_space {:?}", e))
+                })?;
+            Some(stage_space)
+        } else {
+            None
+        };
+
         let view_configuration_type = ViewConfigurationType::PRIMARY_STEREO;
         let view_configurations = instance
             .enumerate_view_configuration_views(system, view_configuration_type)
@@ -991,6 +1033,9 @@ impl OpenXrDevice {
             session,
             frame_waiter,
             viewer_space,
+            // The stage can't be located until the first frame, which sends its bounds
+            needs_bounds_update: stage_space.is_some(),
+            stage_space,
             clip_planes: Default::default(),
             supports_secondary,
             layer_manager,
@@ -1005,6 +1050,39 @@ impl OpenXrDevice {
         })
     }
 
+    /// The stage bounds, which are a rectangle centered on the stage origin,
+    /// located in our floor space as of the latest frame
+    fn stage_bounds(&self, data: &SharedData) -> Option<Vec<Point3D<f32, Floor>>> {
+        let stage_space = self.stage_space.as_ref()?;
+        let extent = self
+            .session
+            .reference_space_bounds_rect(ReferenceSpaceType::STAGE)
+            .ok()??;
+        let time = data.frame_state.as_ref()?.predicted_display_time;
+        let location = stage_space.locate(&data.space, time).ok()?;
+        let pose_valid = location
+            .location_flags
+            .contains(SpaceLocationFlags::POSITION_VALID | SpaceLocationFlags::ORIENTATION_VALID);
+        if !pose_valid {
+            return None;
+        }
+        let stage: RigidTransform3D<f32, Stage, Native> = transform(&location.pose);
+        let stage = stage.post_transform(&self.floor_transform()?);
+        let x = extent.width / 2.0;
+        let z = extent.height / 2.0;
+        let corners = [(-x, -z), (x, -z), (x, z), (-x, z)];
+        let bounds = corners
+            .iter()
+            .map(|&(x, z)| {
+                let point = stage.rotation.transform_point3d(Point3D::new(x, 0.0, z));
+                let point = point + stage.translation;
+                // The stage floor may not quite match ours, so the bounds are put on our floor
+                Point3D::new(point.x, 0.0, point.z)
+            })
+            .collect();
+        Some(bounds)
+    }
+
     fn handle_openxr_events(&mut self) -> bool {
         use openxr::Event::*;
         let mut stopped = false;
@@ -1055,6 +1133,36 @@ impl OpenXrDevice {
                     self.events.callback(Event::SessionEnd);
                     return false;
                 }
+                Some(ReferenceSpaceChangePending(change)) => {
+                    let reset = if change.pose_valid() {
+                        Some(transform(&change.pose_in_previous_space()))
+                    } else {
+                        None
+                    };
+                    match change.reference_space_type() {
+                        ReferenceSpaceType::LOCAL => {
+                            // Our floor is a fixed height below the local space, and
+                            // recentering only turns around the vertical axis,
+                            // so the floor is reset in the same way.
+                            // Bounded floor spaces are backed by our floor too.
+                            self.events
+                                .callback(Event::ReferenceSpaceReset(BaseSpace::Local, reset));
+                            self.events
+                                .callback(Event::ReferenceSpaceReset(BaseSpace::Floor, reset));
+                            self.events.callback(Event::ReferenceSpaceReset(
+                                BaseSpace::BoundedFloor,
+                                reset,
+                            ));
+                            // The stage stays put, so its bounds move within our floor
+                            self.needs_bounds_update = self.stage_space.is_some();
+                        }
+                        ReferenceSpaceType::STAGE => {
+                            // Our floor doesn't move with the stage, but its bounds do
+                            self.needs_bounds_update = self.stage_space.is_some();
+                        }
+                        _ => (),
+                    }
+                }
                 Some(_) => {
                     // FIXME: Handle other events
                 }
@@ -1113,6 +1221,11 @@ impl DeviceAPI for OpenXrDevice {
         Some(RigidTransform3D::from_translation(translation))
     }
 
+    fn bounds_geometry(&self) -> Option<Vec<Point3D<f32, Floor>>> {
+        let guard = self.shared_data.lock().unwrap();
+        self.stage_bounds(guard.as_ref()?)
+    }
+
     fn viewports(&self) -> Viewports {
         self.shared_data
             .lock()
@@ -1240,6 +1353,13 @@ impl DeviceAPI for OpenXrDevice {
         data.frame_state = Some(frame_state);
         let views = data.views();
 
+        let mut events = vec![];
+        if self.needs_bounds_update {
+            self.needs_bounds_update = false;
+            let bounds = self.stage_bounds(data);
+            events.push(FrameUpdateEvent::UpdateBoundsGeometry(bounds));
+        }
+
         if (left.menu_selected || right.menu_selected) && self.context_menu_future.is_none() {
             self.context_menu_future = Some(self.context_menu_provider.open_context_menu());
         } else if self.context_menu_future.is_some() {
@@ -1259,11 +1379,13 @@ impl DeviceAPI for OpenXrDevice {
         let frame = Frame {
             pose: Some(ViewerPose { transform, views }),
             inputs: vec![right.frame, left.frame],
-            events: vec![],
+            events,
             time_ns,
             sub_images,
             sent_time: 0,
             hit_test_results: vec![],
+            transient_hit_test_results: vec![],
+            anchors: vec![],
         };
 
         if let Some(right_select) = right.select {
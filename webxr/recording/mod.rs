@@ -0,0 +1,478 @@
+/* This Source Code Form is subject to the terms of the Mozilla Public
+ * License, v. 2.0. If a copy of the MPL was not distributed with this
+ * file, You can obtain one at https://mozilla.org/MPL/2.0/. */
+
+//! Recording sessions from a device, and replaying them.
+//!
+//! A `RecordingDevice` wraps another device, and writes the frames and events
+//! it produces. A `ReplayDiscovery` reads such a recording back, and serves
+//! it as a session with the same timing as the original.
+
+use crate::SurfmanGL;
+use crate::SurfmanLayerManager;
+
+use bincode::ErrorKind;
+
+use euclid::Point3D;
+use euclid::RigidTransform3D;
+
+use log::warn;
+
+use serde::{Deserialize, Serialize};
+
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use surfman_chains::SwapChains;
+
+use webxr_api::AnchorId;
+use webxr_api::AnchorSource;
+use webxr_api::ContextId;
+use webxr_api::DeviceAPI;
+use webxr_api::DeviceCapabilities;
+use webxr_api::DiscoveryAPI;
+use webxr_api::EnvironmentBlendMode;
+use webxr_api::Error;
+use webxr_api::Event;
+use webxr_api::EventBuffer;
+use webxr_api::Floor;
+use webxr_api::Frame;
+use webxr_api::FrameUpdateEvent;
+use webxr_api::HitTestId;
+use webxr_api::HitTestSource;
+use webxr_api::InputSource;
+use webxr_api::LayerGrandManager;
+use webxr_api::LayerId;
+use webxr_api::LayerInit;
+use webxr_api::LayerManager;
+use webxr_api::Native;
+use webxr_api::Quitter;
+use webxr_api::Sender;
+use webxr_api::Session;
+use webxr_api::SessionBuilder;
+use webxr_api::SessionInit;
+use webxr_api::SessionMode;
+use webxr_api::TransientHitTestSource;
+use webxr_api::Viewports;
+use webxr_api::Visibility;
+
+/// The state of a device at the start of a recording
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct RecordedSession {
+    mode: SessionMode,
+    floor_transform: Option<RigidTransform3D<f32, Native, Floor>>,
+    bounds_geometry: Option<Vec<Point3D<f32, Floor>>>,
+    viewports: Viewports,
+    initial_inputs: Vec<InputSource>,
+    environment_blend_mode: EnvironmentBlendMode,
+    granted_features: Vec<String>,
+    capabilities: DeviceCapabilities,
+}
+
+/// The entries in a recording. Timestamps are in nanoseconds since the start of the recording.
+/// Frame update events are recorded as part of their frame.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+enum Record {
+    Session(RecordedSession),
+    Frame(u64, Frame),
+    Event(u64, Event),
+}
+
+struct Recorder {
+    writer: Box<dyn Write + Send>,
+    start: u64,
+}
+
+impl Recorder {
+    fn write(&mut self, record: &Record) {
+        if let Err(err) = bincode::serialize_into(&mut self.writer, record) {
+            warn!("Failed to write XR recording ({:?})", err);
+        }
+    }
+
+    fn elapsed(&self) -> u64 {
+        time::precise_time_ns().saturating_sub(self.start)
+    }
+
+    fn frame(&mut self, frame: Frame) {
+        let record = Record::Frame(self.elapsed(), frame);
+        self.write(&record);
+    }
+
+    fn event(&mut self, event: Event) {
+        let record = Record::Event(self.elapsed(), event);
+        self.write(&record);
+    }
+}
+
+/// A device which records the frames and events produced by another device.
+pub struct RecordingDevice<Device> {
+    device: Device,
+    recorder: Arc<Mutex<Recorder>>,
+}
+
+impl<Device: DeviceAPI> RecordingDevice<Device> {
+    pub fn new<W>(device: Device, mode: SessionMode, writer: W) -> RecordingDevice<Device>
+    where
+        W: 'static + Write + Send,
+    {
+        let session = RecordedSession {
+            mode,
+            floor_transform: device.floor_transform(),
+            bounds_geometry: device.bounds_geometry(),
+            viewports: device.viewports(),
+            initial_inputs: device.initial_inputs(),
+            environment_blend_mode: device.environment_blend_mode(),
+            granted_features: device.granted_features().to_vec(),
+            capabilities: device.capabilities(),
+        };
+        let mut recorder = Recorder {
+            writer: Box::new(writer),
+            start: time::precise_time_ns(),
+        };
+        recorder.write(&Record::Session(session));
+        RecordingDevice {
+            device,
+            recorder: Arc::new(Mutex::new(recorder)),
+        }
+    }
+}
+
+impl<Device: DeviceAPI> DeviceAPI for RecordingDevice<Device> {
+    fn create_layer(&mut self, context_id: ContextId, init: LayerInit) -> Result<LayerId, Error> {
+        self.device.create_layer(context_id, init)
+    }
+
+    fn destroy_layer(&mut self, context_id: ContextId, layer_id: LayerId) {
+        self.device.destroy_layer(context_id, layer_id)
+    }
+
+    fn floor_transform(&self) -> Option<RigidTransform3D<f32, Native, Floor>> {
+        self.device.floor_transform()
+    }
+
+    fn bounds_geometry(&self) -> Option<Vec<Point3D<f32, Floor>>> {
+        self.device.bounds_geometry()
+    }
+
+    fn viewports(&self) -> Viewports {
+        self.device.viewports()
+    }
+
+    fn begin_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) -> Option<Frame> {
+        let frame = self.device.begin_animation_frame(layers)?;
+        self.recorder.lock().unwrap().frame(frame.clone());
+        Some(frame)
+    }
+
+    fn end_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) {
+        self.device.end_animation_frame(layers)
+    }
+
+    fn initial_inputs(&self) -> Vec<InputSource> {
+        self.device.initial_inputs()
+    }
+
+    fn set_event_dest(&mut self, dest: Sender<Event>) {
+        // Events are sent asynchronously by the device, so we interpose
+        // a thread which records them before passing them on.
+        let (sender, receiver) = match webxr_api::channel() {
+            Ok(channel) => channel,
+            Err(_) => {
+                warn!("Failed to create channel, events will not be recorded");
+                return self.device.set_event_dest(dest);
+            }
+        };
+        let recorder = self.recorder.clone();
+        thread::spawn(move || {
+            while let Ok(event) = receiver.recv() {
+                recorder.lock().unwrap().event(event.clone());
+                let _ = dest.send(event);
+            }
+        });
+        self.device.set_event_dest(sender)
+    }
+
+    fn quit(&mut self) {
+        self.device.quit();
+        let _ = self.recorder.lock().unwrap().writer.flush();
+    }
+
+    fn set_quitter(&mut self, quitter: Quitter) {
+        self.device.set_quitter(quitter)
+    }
+
+    fn update_clip_planes(&mut self, near: f32, far: f32) {
+        self.device.update_clip_planes(near, far)
+    }
+
+    fn environment_blend_mode(&self) -> EnvironmentBlendMode {
+        self.device.environment_blend_mode()
+    }
+
+    fn granted_features(&self) -> &[String] {
+        self.device.granted_features()
+    }
+
+    fn set_visibility(&mut self, visibility: Visibility) {
+        self.device.set_visibility(visibility)
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        self.device.capabilities()
+    }
+
+    fn request_hit_test(&mut self, source: HitTestSource) {
+        self.device.request_hit_test(source)
+    }
+
+    fn request_hit_test_for_transient_input(&mut self, source: TransientHitTestSource) {
+        self.device.request_hit_test_for_transient_input(source)
+    }
+
+    fn cancel_hit_test(&mut self, id: HitTestId) {
+        self.device.cancel_hit_test(id)
+    }
+
+    fn create_anchor(&mut self, source: AnchorSource) -> Result<AnchorId, Error> {
+        self.device.create_anchor(source)
+    }
+
+    fn delete_anchor(&mut self, id: AnchorId) {
+        self.device.delete_anchor(id)
+    }
+}
+
+/// A discovery which replays a recorded session.
+pub struct ReplayDiscovery {
+    session: RecordedSession,
+    records: Arc<Vec<Record>>,
+}
+
+impl ReplayDiscovery {
+    pub fn new<R: Read>(mut reader: R) -> Result<ReplayDiscovery, Error> {
+        let session = match bincode::deserialize_from(&mut reader) {
+            Ok(Record::Session(session)) => session,
+            Ok(_) => {
+                return Err(Error::BackendSpecific(
+                    "XR recording does not start with a session".into(),
+                ))
+            }
+            Err(err) => return Err(Error::BackendSpecific(format!("{:?}", err))),
+        };
+        let mut records = Vec::new();
+        loop {
+            match bincode::deserialize_from(&mut reader) {
+                Ok(record) => records.push(record),
+                Err(err) => match *err {
+                    ErrorKind::Io(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                    _ => return Err(Error::BackendSpecific(format!("{:?}", err))),
+                },
+            }
+        }
+        Ok(ReplayDiscovery {
+            session,
+            records: Arc::new(records),
+        })
+    }
+}
+
+impl DiscoveryAPI<SurfmanGL> for ReplayDiscovery {
+    fn request_session(
+        &mut self,
+        mode: SessionMode,
+        init: &SessionInit,
+        xr: SessionBuilder<SurfmanGL>,
+    ) -> Result<Session, Error> {
+        if !self.supports_session(mode) {
+            return Err(Error::NoMatchingDevice);
+        }
+        let granted_features = init.validate(mode, &self.session.granted_features)?;
+        let session = self.session.clone();
+        let records = self.records.clone();
+        xr.spawn(move |grand_manager| {
+            Ok(ReplayDevice {
+                session,
+                records,
+                next_record: 0,
+                next_anchor_id: 0,
+                start: None,
+                events: Default::default(),
+                granted_features,
+                grand_manager,
+                layer_manager: None,
+            })
+        })
+    }
+
+    fn supports_session(&self, mode: SessionMode) -> bool {
+        self.session.mode == mode
+    }
+}
+
+struct ReplayDevice {
+    session: RecordedSession,
+    records: Arc<Vec<Record>>,
+    next_record: usize,
+    next_anchor_id: u32,
+    start: Option<u64>,
+    events: EventBuffer,
+    granted_features: Vec<String>,
+    grand_manager: LayerGrandManager<SurfmanGL>,
+    layer_manager: Option<LayerManager>,
+}
+
+impl ReplayDevice {
+    fn layer_manager(&mut self) -> Result<&mut LayerManager, Error> {
+        if let Some(ref mut manager) = self.layer_manager {
+            return Ok(manager);
+        }
+        let swap_chains = SwapChains::new();
+        let viewports = self.viewports();
+        let layer_manager = self.grand_manager.create_layer_manager(move |_, _| {
+            Ok(SurfmanLayerManager::new(viewports, swap_chains))
+        })?;
+        self.layer_manager = Some(layer_manager);
+        Ok(self.layer_manager.as_mut().unwrap())
+    }
+}
+
+/// Block until the given value of `time::precise_time_ns()`
+fn wait_until(time_ns: u64) {
+    let now = time::precise_time_ns();
+    if time_ns > now {
+        thread::sleep(Duration::from_nanos(time_ns - now));
+    }
+}
+
+impl DeviceAPI for ReplayDevice {
+    fn create_layer(&mut self, context_id: ContextId, init: LayerInit) -> Result<LayerId, Error> {
+        self.layer_manager()?.create_layer(context_id, init)
+    }
+
+    fn destroy_layer(&mut self, context_id: ContextId, layer_id: LayerId) {
+        self.layer_manager()
+            .unwrap()
+            .destroy_layer(context_id, layer_id)
+    }
+
+    fn floor_transform(&self) -> Option<RigidTransform3D<f32, Native, Floor>> {
+        self.session.floor_transform.clone()
+    }
+
+    fn bounds_geometry(&self) -> Option<Vec<Point3D<f32, Floor>>> {
+        self.session.bounds_geometry.clone()
+    }
+
+    fn viewports(&self) -> Viewports {
+        self.session.viewports.clone()
+    }
+
+    fn begin_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) -> Option<Frame> {
+        let start = *self.start.get_or_insert_with(time::precise_time_ns);
+        let records = self.records.clone();
+        while let Some(record) = records.get(self.next_record) {
+            self.next_record += 1;
+            match record {
+                Record::Event(offset, event) => {
+                    wait_until(start + offset);
+                    self.events.callback(event.clone());
+                }
+                Record::Frame(offset, frame) => {
+                    wait_until(start + offset);
+                    for event in &frame.events {
+                        match event {
+                            FrameUpdateEvent::UpdateFloorTransform(floor_transform) => {
+                                self.session.floor_transform = floor_transform.clone()
+                            }
+                            FrameUpdateEvent::UpdateViewports(viewports) => {
+                                self.session.viewports = viewports.clone();
+                                // Layers are resized before the frame begins,
+                                // so that its sub images match its views
+                                let layer_manager = self.layer_manager().ok()?;
+                                if let Err(err) = layer_manager.set_viewports(viewports.clone()) {
+                                    warn!("Failed to resize layers: {:?}", err);
+                                }
+                            }
+                            FrameUpdateEvent::UpdateBoundsGeometry(bounds_geometry) => {
+                                self.session.bounds_geometry = bounds_geometry.clone()
+                            }
+                            _ => (),
+                        }
+                    }
+                    // The recorded textures belong to the original session,
+                    // so we replace them by our own.
+                    let sub_images = self.layer_manager().ok()?.begin_frame(layers).ok()?;
+                    let mut frame = frame.clone();
+                    frame.time_ns = start + offset;
+                    frame.sub_images = sub_images;
+                    return Some(frame);
+                }
+                Record::Session(_) => warn!("Ignoring unexpected session in XR recording"),
+            }
+        }
+        // The recording has finished
+        None
+    }
+
+    fn end_animation_frame(&mut self, layers: &[(ContextId, LayerId)]) {
+        let _ = self.layer_manager().unwrap().end_frame(layers);
+    }
+
+    fn initial_inputs(&self) -> Vec<InputSource> {
+        self.session.initial_inputs.clone()
+    }
+
+    fn set_event_dest(&mut self, dest: Sender<Event>) {
+        self.events.upgrade(dest)
+    }
+
+    fn quit(&mut self) {
+        self.events.callback(Event::SessionEnd);
+    }
+
+    fn set_quitter(&mut self, _: Quitter) {
+        // The replay ends by itself when the recording runs out
+    }
+
+    fn update_clip_planes(&mut self, _: f32, _: f32) {
+        // The recorded views already have their projections
+    }
+
+    fn environment_blend_mode(&self) -> EnvironmentBlendMode {
+        self.session.environment_blend_mode
+    }
+
+    fn granted_features(&self) -> &[String] {
+        &self.granted_features
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        DeviceCapabilities {
+            features: self.granted_features.clone(),
+            ..self.session.capabilities.clone()
+        }
+    }
+
+    fn request_hit_test(&mut self, _: HitTestSource) {
+        // Hit test results are replayed from the recording
+    }
+
+    fn request_hit_test_for_transient_input(&mut self, _: TransientHitTestSource) {}
+
+    fn cancel_hit_test(&mut self, _: HitTestId) {}
+
+    fn create_anchor(&mut self, _: AnchorSource) -> Result<AnchorId, Error> {
+        // Anchor poses are replayed from the recording, which will match
+        // if the anchors are created in the same order as the original session
+        let id = AnchorId(self.next_anchor_id);
+        self.next_anchor_id += 1;
+        Ok(id)
+    }
+
+    fn delete_anchor(&mut self, _: AnchorId) {}
+}
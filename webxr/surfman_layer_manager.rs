@@ -7,6 +7,7 @@
 use euclid::Point2D;
 use euclid::Rect;
 use euclid::Size2D;
+use euclid::UnknownUnit;
 
 use sparkle::gl;
 use sparkle::gl::GLuint;
@@ -28,9 +29,11 @@ use webxr_api::GLContexts;
 use webxr_api::GLTypes;
 use webxr_api::LayerId;
 use webxr_api::LayerInit;
+use webxr_api::LayerLayout;
 use webxr_api::LayerManagerAPI;
 use webxr_api::SubImage;
 use webxr_api::SubImages;
+use webxr_api::Viewport;
 use webxr_api::Viewports;
 
 #[derive(Copy, Clone, Debug)]
@@ -42,11 +45,152 @@ impl GLTypes for SurfmanGL {
     type Bindings = Gl;
 }
 
+/// The textures which are rendered to by layers with a texture array layout,
+/// which are copied into the layer's surface at the end of each frame
+struct TextureArray {
+    color_texture: GLuint,
+    depth_stencil_texture: Option<GLuint>,
+    /// Where each layer of the array is copied to in the surface
+    view_viewports: Vec<Rect<i32, Viewport>>,
+    /// The size of each layer of the array
+    size: Size2D<i32, Viewport>,
+}
+
+impl TextureArray {
+    fn new(gl: &Gl, view_viewports: Vec<Rect<i32, Viewport>>, depth_stencil: bool) -> TextureArray {
+        let size =
+            view_viewports
+                .iter()
+                .fold(Size2D::zero(), |size: Size2D<i32, Viewport>, viewport| {
+                    Size2D::new(
+                        size.width.max(viewport.size.width),
+                        size.height.max(viewport.size.height),
+                    )
+                });
+        let layers = view_viewports.len() as i32;
+        let color_texture = gl.gen_textures(1)[0];
+        gl.bind_texture(gl::TEXTURE_2D_ARRAY, color_texture);
+        gl.tex_storage_3d(
+            gl::TEXTURE_2D_ARRAY,
+            1,
+            gl::RGBA8,
+            size.width,
+            size.height,
+            layers,
+        );
+        let depth_stencil_texture = if depth_stencil {
+            let depth_stencil_texture = gl.gen_textures(1)[0];
+            gl.bind_texture(gl::TEXTURE_2D_ARRAY, depth_stencil_texture);
+            gl.tex_storage_3d(
+                gl::TEXTURE_2D_ARRAY,
+                1,
+                gl::DEPTH24_STENCIL8,
+                size.width,
+                size.height,
+                layers,
+            );
+            Some(depth_stencil_texture)
+        } else {
+            None
+        };
+        gl.bind_texture(gl::TEXTURE_2D_ARRAY, 0);
+        TextureArray {
+            color_texture,
+            depth_stencil_texture,
+            view_viewports,
+            size,
+        }
+    }
+
+    fn view_sub_images(&self) -> Vec<SubImage> {
+        let viewport = Rect::new(Point2D::new(0, 0), self.size);
+        (0..self.view_viewports.len() as u32)
+            .map(|index| SubImage {
+                color_texture: self.color_texture,
+                depth_stencil_texture: self.depth_stencil_texture,
+                texture_array_index: Some(index),
+                viewport,
+            })
+            .collect()
+    }
+
+    /// Copy each layer of the array into its viewport in the layer's surface
+    fn resolve(&self, gl: &Gl, texture_id: GLuint, texture_target: GLuint) {
+        let mut bound_framebuffers = [0, 0];
+        unsafe {
+            gl.get_integer_v(gl::READ_FRAMEBUFFER_BINDING, &mut bound_framebuffers[0..1]);
+            gl.get_integer_v(gl::DRAW_FRAMEBUFFER_BINDING, &mut bound_framebuffers[1..2]);
+        }
+        let framebuffers = gl.gen_framebuffers(2);
+        gl.bind_framebuffer(gl::READ_FRAMEBUFFER, framebuffers[0]);
+        gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, framebuffers[1]);
+        gl.framebuffer_texture_2d(
+            gl::DRAW_FRAMEBUFFER,
+            gl::COLOR_ATTACHMENT0,
+            texture_target,
+            texture_id,
+            0,
+        );
+        for (index, viewport) in self.view_viewports.iter().enumerate() {
+            gl.framebuffer_texture_layer(
+                gl::READ_FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                self.color_texture,
+                0,
+                index as i32,
+            );
+            gl.blit_framebuffer(
+                0,
+                0,
+                viewport.size.width,
+                viewport.size.height,
+                viewport.min_x(),
+                viewport.min_y(),
+                viewport.max_x(),
+                viewport.max_y(),
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+        }
+        gl.bind_framebuffer(gl::READ_FRAMEBUFFER, bound_framebuffers[0] as GLuint);
+        gl.bind_framebuffer(gl::DRAW_FRAMEBUFFER, bound_framebuffers[1] as GLuint);
+        gl.delete_framebuffers(&framebuffers);
+    }
+
+    fn delete(&self, gl: &Gl) {
+        gl.delete_textures(&[self.color_texture]);
+        if let Some(depth_stencil_texture) = self.depth_stencil_texture {
+            gl.delete_textures(&[depth_stencil_texture]);
+        }
+    }
+}
+
+fn allocate_depth_stencil_texture(gl: &Gl, texture: GLuint, size: Size2D<i32, UnknownUnit>) {
+    gl.bind_texture(gl::TEXTURE_2D, texture);
+    gl.tex_image_2d(
+        gl::TEXTURE_2D,
+        0,
+        gl::DEPTH24_STENCIL8 as _,
+        size.width,
+        size.height,
+        0,
+        gl::DEPTH_STENCIL,
+        gl::UNSIGNED_INT_24_8,
+        gl::TexImageSource::Pixels(None),
+    );
+}
+
 pub struct SurfmanLayerManager {
     layers: Vec<(ContextId, LayerId)>,
+    inits: HashMap<LayerId, LayerInit>,
     swap_chains: SwapChains<LayerId, SurfmanDevice>,
     surface_textures: HashMap<LayerId, SurfaceTexture>,
     depth_stencil_textures: HashMap<LayerId, GLuint>,
+    /// The views of layers which aren't laid out like the device's views
+    view_viewports: HashMap<LayerId, Vec<Rect<i32, Viewport>>>,
+    /// The static layers, and whether they have been drawn
+    static_layers: HashMap<LayerId, bool>,
+    texture_arrays: HashMap<LayerId, TextureArray>,
     viewports: Viewports,
 }
 
@@ -56,13 +200,21 @@ impl SurfmanLayerManager {
         swap_chains: SwapChains<LayerId, SurfmanDevice>,
     ) -> SurfmanLayerManager {
         let layers = Vec::new();
+        let inits = HashMap::new();
         let surface_textures = HashMap::new();
         let depth_stencil_textures = HashMap::new();
+        let view_viewports = HashMap::new();
+        let static_layers = HashMap::new();
+        let texture_arrays = HashMap::new();
         SurfmanLayerManager {
             layers,
+            inits,
             swap_chains,
             surface_textures,
             depth_stencil_textures,
+            view_viewports,
+            static_layers,
+            texture_arrays,
             viewports,
         }
     }
@@ -80,38 +232,40 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
         let layer_id = LayerId::new();
         let access = SurfaceAccess::GPUOnly;
         let size = texture_size.to_untyped();
-        // TODO: Treat depth and stencil separately?
-        let has_depth_stencil = match init {
-            LayerInit::WebGLLayer { stencil, depth, .. } => stencil | depth,
-            LayerInit::ProjectionLayer { stencil, depth, .. } => stencil | depth,
-        };
-        if has_depth_stencil {
+        let view_viewports = init.view_viewports();
+        if init.layout() == LayerLayout::TextureArray {
+            // Layers which render to a texture array get their depth and stencil from the array
+            let gl = contexts
+                .bindings(device, context_id)
+                .ok_or(Error::ContextNotFound(context_id))?;
+            let view_viewports = view_viewports
+                .clone()
+                .unwrap_or_else(|| self.viewports.viewports.clone());
+            let texture_array = TextureArray::new(gl, view_viewports, init.has_depth_stencil());
+            self.texture_arrays.insert(layer_id, texture_array);
+        } else if init.has_depth_stencil() {
             let gl = contexts
                 .bindings(device, context_id)
-                .ok_or(Error::NoMatchingDevice)?;
+                .ok_or(Error::ContextNotFound(context_id))?;
             let depth_stencil_texture = gl.gen_textures(1)[0];
-            gl.bind_texture(gl::TEXTURE_2D, depth_stencil_texture);
-            gl.tex_image_2d(
-                gl::TEXTURE_2D,
-                0,
-                gl::DEPTH24_STENCIL8 as _,
-                size.width,
-                size.height,
-                0,
-                gl::DEPTH_STENCIL,
-                gl::UNSIGNED_INT_24_8,
-                gl::TexImageSource::Pixels(None),
-            );
+            allocate_depth_stencil_texture(gl, depth_stencil_texture, size);
             self.depth_stencil_textures
                 .insert(layer_id, depth_stencil_texture);
         }
         let context = contexts
             .context(device, context_id)
-            .ok_or(Error::NoMatchingDevice)?;
+            .ok_or(Error::ContextNotFound(context_id))?;
         self.swap_chains
             .create_detached_swap_chain(layer_id, size, device, context, access)
-            .map_err(|err| Error::BackendSpecific(format!("{:?}", err)))?;
+            .map_err(|err| Error::SwapChainFailure(format!("{:?}", err)))?;
+        if let Some(view_viewports) = view_viewports {
+            self.view_viewports.insert(layer_id, view_viewports);
+        }
+        if init.is_static() {
+            self.static_layers.insert(layer_id, false);
+        }
         self.layers.push((context_id, layer_id));
+        self.inits.insert(layer_id, init);
         Ok(layer_id)
     }
 
@@ -128,11 +282,18 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
         };
         self.layers.retain(|&ids| ids != (context_id, layer_id));
         let _ = self.swap_chains.destroy(layer_id, device, context);
+        self.inits.remove(&layer_id);
         self.surface_textures.remove(&layer_id);
+        self.view_viewports.remove(&layer_id);
+        self.static_layers.remove(&layer_id);
         if let Some(depth_stencil_texture) = self.depth_stencil_textures.remove(&layer_id) {
             let gl = contexts.bindings(device, context_id).unwrap();
             gl.delete_textures(&[depth_stencil_texture]);
         }
+        if let Some(texture_array) = self.texture_arrays.remove(&layer_id) {
+            let gl = contexts.bindings(device, context_id).unwrap();
+            texture_array.delete(gl);
+        }
     }
 
     fn layers(&self) -> &[(ContextId, LayerId)] {
@@ -148,18 +309,36 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
         layers
             .iter()
             .map(|&(context_id, layer_id)| {
+                // Static layers which have been drawn have nothing to draw to
+                if self.static_layers.get(&layer_id) == Some(&true) {
+                    return Ok(SubImages {
+                        layer_id,
+                        sub_image: None,
+                        view_sub_images: Vec::new(),
+                    });
+                }
                 let context = contexts
                     .context(device, context_id)
-                    .ok_or(Error::NoMatchingDevice)?;
+                    .ok_or(Error::ContextNotFound(context_id))?;
                 let swap_chain = self
                     .swap_chains
                     .get(layer_id)
-                    .ok_or(Error::NoMatchingDevice)?;
+                    .ok_or(Error::LayerNotFound(layer_id))?;
                 let surface_size = Size2D::from_untyped(swap_chain.size());
                 let surface_texture = swap_chain
                     .take_surface_texture(device, context)
-                    .map_err(|_| Error::NoMatchingDevice)?;
+                    .map_err(|err| Error::SwapChainFailure(format!("{:?}", err)))?;
                 let color_texture = device.surface_texture_object(&surface_texture);
+                self.surface_textures.insert(layer_id, surface_texture);
+                // Layers with a texture array layout render to the array,
+                // which is copied into the surface when the frame ends
+                if let Some(texture_array) = self.texture_arrays.get(&layer_id) {
+                    return Ok(SubImages {
+                        layer_id,
+                        sub_image: None,
+                        view_sub_images: texture_array.view_sub_images(),
+                    });
+                }
                 let depth_stencil_texture = self.depth_stencil_textures.get(&layer_id).cloned();
                 let texture_array_index = None;
                 let origin = Point2D::new(0, 0);
@@ -170,8 +349,9 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
                     viewport: Rect::new(origin, surface_size),
                 });
                 let view_sub_images = self
-                    .viewports
-                    .viewports
+                    .view_viewports
+                    .get(&layer_id)
+                    .unwrap_or(&self.viewports.viewports)
                     .iter()
                     .map(|&viewport| SubImage {
                         color_texture,
@@ -180,7 +360,6 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
                         viewport,
                     })
                     .collect();
-                self.surface_textures.insert(layer_id, surface_texture);
                 Ok(SubImages {
                     layer_id,
                     sub_image,
@@ -197,27 +376,82 @@ impl LayerManagerAPI<SurfmanGL> for SurfmanLayerManager {
         layers: &[(ContextId, LayerId)],
     ) -> Result<(), Error> {
         for &(context_id, layer_id) in layers {
+            if let Some(drawn) = self.static_layers.get_mut(&layer_id) {
+                if *drawn {
+                    continue;
+                }
+                *drawn = true;
+            }
             let gl = contexts
                 .bindings(device, context_id)
-                .ok_or(Error::NoMatchingDevice)?;
+                .ok_or(Error::ContextNotFound(context_id))?;
+            if let Some(texture_array) = self.texture_arrays.get(&layer_id) {
+                if let Some(surface_texture) = self.surface_textures.get(&layer_id) {
+                    let texture_id = device.surface_texture_object(surface_texture);
+                    let texture_target = device.surface_gl_texture_target();
+                    texture_array.resolve(gl, texture_id, texture_target);
+                }
+            }
             gl.flush();
             let context = contexts
                 .context(device, context_id)
-                .ok_or(Error::NoMatchingDevice)?;
-            let surface_texture = self
-                .surface_textures
-                .remove(&layer_id)
-                .ok_or(Error::NoMatchingDevice)?;
+                .ok_or(Error::ContextNotFound(context_id))?;
+            let surface_texture = self.surface_textures.remove(&layer_id).ok_or_else(|| {
+                Error::InvalidState(format!("Layer {:?} ended before it began", layer_id))
+            })?;
             let swap_chain = self
                 .swap_chains
                 .get(layer_id)
-                .ok_or(Error::NoMatchingDevice)?;
+                .ok_or(Error::LayerNotFound(layer_id))?;
             swap_chain
                 .recycle_surface_texture(device, context, surface_texture)
-                .map_err(|err| Error::BackendSpecific(format!("{:?}", err)))?;
+                .map_err(|err| Error::SwapChainFailure(format!("{:?}", err)))?;
             swap_chain
                 .swap_buffers(device, context)
-                .map_err(|err| Error::BackendSpecific(format!("{:?}", err)))?;
+                .map_err(|err| Error::SwapChainFailure(format!("{:?}", err)))?;
+        }
+        Ok(())
+    }
+
+    fn set_viewports(
+        &mut self,
+        device: &mut SurfmanDevice,
+        contexts: &mut dyn GLContexts<SurfmanGL>,
+        viewports: Viewports,
+    ) -> Result<(), Error> {
+        if self.viewports == viewports {
+            return Ok(());
+        }
+        self.viewports = viewports;
+        for (context_id, layer_id) in self.layers.clone() {
+            // Only layers which are split into the device's viewports depend on them
+            let init = match self.inits.get(&layer_id) {
+                Some(init) if init.view_viewports().is_none() => *init,
+                _ => continue,
+            };
+            let size = init.texture_size(&self.viewports).to_untyped();
+            let gl = contexts
+                .bindings(device, context_id)
+                .ok_or(Error::ContextNotFound(context_id))?;
+            if let Some(texture_array) = self.texture_arrays.remove(&layer_id) {
+                texture_array.delete(gl);
+                let view_viewports = self.viewports.viewports.clone();
+                let texture_array = TextureArray::new(gl, view_viewports, init.has_depth_stencil());
+                self.texture_arrays.insert(layer_id, texture_array);
+            }
+            if let Some(&depth_stencil_texture) = self.depth_stencil_textures.get(&layer_id) {
+                allocate_depth_stencil_texture(gl, depth_stencil_texture, size);
+            }
+            let context = contexts
+                .context(device, context_id)
+                .ok_or(Error::ContextNotFound(context_id))?;
+            let swap_chain = self
+                .swap_chains
+                .get(layer_id)
+                .ok_or(Error::LayerNotFound(layer_id))?;
+            swap_chain
+                .resize(device, context, size)
+                .map_err(|err| Error::SwapChainFailure(format!("{:?}", err)))?;
         }
         Ok(())
     }